@@ -0,0 +1,398 @@
+// ASSET CODE
+
+use bevy::asset::{AssetServerSettings, FileAssetIo, HandleId, LoadState};
+use bevy::prelude::*;
+use bevy_kira_audio::AudioSource;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use crate::logic::state::{GameState, LoadingProgress};
+
+// Every file the game loads from the assets folder.
+// Nothing should call the asset server with a path that isn't in here.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum GameAsset {
+    Background,
+    LargeBlockTexture,
+    // The smallest blocks use a tinted copy of this too
+    MediumBlockTexture,
+    PlayerTexture,
+    Font,
+    BackgroundMusic,
+}
+
+impl GameAsset {
+    pub const ALL: [GameAsset; 6] = [
+        GameAsset::Background,
+        GameAsset::LargeBlockTexture,
+        GameAsset::MediumBlockTexture,
+        GameAsset::PlayerTexture,
+        GameAsset::Font,
+        GameAsset::BackgroundMusic,
+    ];
+
+    // Relative to the assets folder, spelled exactly as it is on disk
+    pub fn path(self) -> &'static str {
+        match self {
+            GameAsset::Background => "textures/bg.png",
+            GameAsset::LargeBlockTexture => "textures/block_1.png",
+            GameAsset::MediumBlockTexture => "textures/block_2.png",
+            GameAsset::PlayerTexture => "textures/block_3.png",
+            GameAsset::Font => "fonts/Roboto-Thin.ttf",
+            GameAsset::BackgroundMusic => "sounds/bg_music.mp3",
+        }
+    }
+
+    // What the game does without the file, for the startup report
+    fn fallback(self) -> &'static str {
+        match self {
+            GameAsset::Background => "showing a plain background",
+            GameAsset::LargeBlockTexture
+            | GameAsset::MediumBlockTexture
+            | GameAsset::PlayerTexture => "drawing a plain colored square",
+            GameAsset::Font => "using the font built into the game",
+            GameAsset::BackgroundMusic => "playing without music",
+        }
+    }
+
+    fn is_texture(self) -> bool {
+        !matches!(self, GameAsset::Font | GameAsset::BackgroundMusic)
+    }
+
+    // Used instead of the texture when it is missing
+    fn fallback_color(self) -> Color {
+        match self {
+            GameAsset::LargeBlockTexture => Color::rgb(0.55, 0.55, 0.6),
+            GameAsset::MediumBlockTexture => Color::rgb(0.7, 0.7, 0.75),
+            GameAsset::PlayerTexture => Color::rgb(0.3, 0.7, 1.0),
+            _ => Color::NONE,
+        }
+    }
+}
+
+// Checks the manifest against the assets folder once the window opens,
+// then loads everything in it while the loading screen is up.
+// Needs the asset plugins, so it is only added on top of DefaultPlugins.
+pub struct AssetsPlugin;
+
+impl Plugin for AssetsPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<GameAssets>();
+
+        let total = app
+            .world()
+            .get_resource::<GameAssets>()
+            .expect("The game assets were just added.")
+            .handle_ids()
+            .len();
+
+        app.insert_resource(LoadingProgress { loaded: 0, total })
+            .add_system_set(
+                SystemSet::on_update(GameState::Loading).with_system(track_loading.system()),
+            );
+    }
+}
+
+// Handles to every asset in the manifest, loaded once up front.
+// Anything missing or broken is left out and its fallback is used instead.
+pub struct GameAssets {
+    textures: HashMap<GameAsset, Handle<Texture>>,
+    font: Option<Handle<Font>>,
+    music: Option<Handle<AudioSource>>,
+    fallback_font: Handle<Font>,
+}
+
+impl FromWorld for GameAssets {
+    fn from_world(world: &mut World) -> Self {
+        let asset_folder = world
+            .get_resource::<AssetServerSettings>()
+            .map(|settings| settings.asset_folder.clone())
+            .unwrap_or_else(|| "assets".to_string());
+        let root = FileAssetIo::get_root_path().join(asset_folder);
+
+        // The path to load each asset from, missing assets aren't in here
+        let mut found = HashMap::new();
+        let mut problems = Vec::new();
+
+        for asset in GameAsset::ALL.iter().copied() {
+            match find_asset(&root, asset.path()) {
+                Lookup::Found => {
+                    found.insert(asset, asset.path().to_string());
+                }
+                Lookup::WrongCase(actual) => {
+                    problems.push(format!(
+                        "{} is spelled {} on disk, loading that instead.",
+                        asset.path(),
+                        actual
+                    ));
+                    found.insert(asset, actual);
+                }
+                Lookup::Missing => {
+                    problems.push(format!(
+                        "{} is missing, {}.",
+                        asset.path(),
+                        asset.fallback()
+                    ));
+                }
+            }
+        }
+
+        if problems.is_empty() {
+            println!(
+                "Found all {} assets in {}.",
+                GameAsset::ALL.len(),
+                root.display()
+            );
+        } else {
+            println!(
+                "Found {} of {} assets in {}:",
+                found.len(),
+                GameAsset::ALL.len(),
+                root.display()
+            );
+            for problem in problems {
+                println!("  - {}", problem);
+            }
+        }
+
+        // The font is small enough to keep a copy in the binary,
+        // so the text still shows up without the assets folder
+        let font = Font::try_from_bytes(include_bytes!("../assets/fonts/Roboto-Thin.ttf").to_vec())
+            .expect("The built in font is a valid font.");
+        let fallback_font = world
+            .get_resource_mut::<Assets<Font>>()
+            .expect("The text plugin is added before the assets plugin.")
+            .add(font);
+
+        let asset_server = world
+            .get_resource::<AssetServer>()
+            .expect("The asset plugin is added before the assets plugin.");
+        let load = |asset: GameAsset| {
+            found
+                .get(&asset)
+                .map(|path| asset_server.load_untyped(path.as_str()))
+        };
+
+        GameAssets {
+            textures: GameAsset::ALL
+                .iter()
+                .filter(|asset| asset.is_texture())
+                .filter_map(|asset| Some((*asset, load(*asset)?.typed())))
+                .collect(),
+            font: load(GameAsset::Font).map(HandleUntyped::typed),
+            music: load(GameAsset::BackgroundMusic).map(HandleUntyped::typed),
+            fallback_font,
+        }
+    }
+}
+
+impl GameAssets {
+    // None if the texture is missing
+    pub fn texture(&self, asset: GameAsset) -> Option<Handle<Texture>> {
+        self.textures.get(&asset).cloned()
+    }
+
+    // The texture tinted by the color, or a plain square of color if the texture is missing
+    pub fn material(&self, asset: GameAsset, tint: Color) -> ColorMaterial {
+        match self.texture(asset) {
+            Some(texture) => ColorMaterial::modulated_texture(texture, tint),
+            None => ColorMaterial::color(asset.fallback_color()),
+        }
+    }
+
+    pub fn font(&self) -> Handle<Font> {
+        self.font
+            .clone()
+            .unwrap_or_else(|| self.fallback_font.clone())
+    }
+
+    // Always ready, for text shown before everything has loaded
+    pub fn fallback_font(&self) -> Handle<Font> {
+        self.fallback_font.clone()
+    }
+
+    pub fn music(&self) -> Option<Handle<AudioSource>> {
+        self.music.clone()
+    }
+
+    // Everything still being loaded
+    fn handle_ids(&self) -> Vec<(GameAsset, HandleId)> {
+        let textures = self
+            .textures
+            .iter()
+            .map(|(asset, handle)| (*asset, handle.id));
+        let font = self.font.iter().map(|handle| (GameAsset::Font, handle.id));
+        let music = self
+            .music
+            .iter()
+            .map(|handle| (GameAsset::BackgroundMusic, handle.id));
+
+        textures.chain(font).chain(music).collect()
+    }
+
+    // Stops using an asset that failed to load, so its fallback is used instead
+    fn forget(&mut self, asset: GameAsset) {
+        match asset {
+            GameAsset::Font => self.font = None,
+            GameAsset::BackgroundMusic => self.music = None,
+            _ => {
+                self.textures.remove(&asset);
+            }
+        }
+    }
+}
+
+// A file can be there and still fail to load, e.g. a broken image
+fn track_loading(
+    asset_server: Res<AssetServer>,
+    mut game_assets: ResMut<GameAssets>,
+    mut loading_progress: ResMut<LoadingProgress>,
+) {
+    let mut loaded = 0;
+
+    for (asset, id) in game_assets.handle_ids() {
+        match asset_server.get_load_state(id) {
+            LoadState::Loaded => loaded += 1,
+            LoadState::Failed => {
+                println!("Could not load {}, {}.", asset.path(), asset.fallback());
+                game_assets.forget(asset);
+            }
+            _ => {}
+        }
+    }
+
+    let total = game_assets.handle_ids().len();
+
+    // Only touched when it changes, so the loading screen knows when to redraw
+    if loading_progress.loaded != loaded || loading_progress.total != total {
+        loading_progress.loaded = loaded;
+        loading_progress.total = total;
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Lookup {
+    Found,
+    // Only there with different capitals, which breaks on case sensitive file systems.
+    // Holds the path as it is really spelled.
+    WrongCase(String),
+    Missing,
+}
+
+// Looks for the file one folder at a time and compares the names exactly,
+// so a misspelled capital is caught on every file system
+fn find_asset(root: &Path, relative_path: &str) -> Lookup {
+    let mut directory = root.to_path_buf();
+    let mut actual_path = Vec::new();
+    let mut wrong_case = false;
+
+    for name in relative_path.split('/') {
+        let entries: Vec<String> = match fs::read_dir(&directory) {
+            Ok(entries) => entries
+                .filter_map(|entry| entry.ok())
+                .filter_map(|entry| entry.file_name().into_string().ok())
+                .collect(),
+            Err(_) => return Lookup::Missing,
+        };
+
+        let entry = if entries.iter().any(|entry| entry == name) {
+            name.to_string()
+        } else {
+            match entries
+                .into_iter()
+                .find(|entry| entry.eq_ignore_ascii_case(name))
+            {
+                Some(entry) => {
+                    wrong_case = true;
+                    entry
+                }
+                None => return Lookup::Missing,
+            }
+        };
+
+        directory.push(&entry);
+        actual_path.push(entry);
+    }
+
+    if !directory.is_file() {
+        Lookup::Missing
+    } else if wrong_case {
+        Lookup::WrongCase(actual_path.join("/"))
+    } else {
+        Lookup::Found
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::testing::TemporaryDirectory;
+
+    // A folder of its own for each test, with just the one font in it
+    fn temporary_root(name: &str) -> TemporaryDirectory {
+        let root = TemporaryDirectory::new(name);
+        fs::create_dir_all(root.join("fonts")).unwrap();
+        fs::write(root.join("fonts/Roboto-Thin.ttf"), "").unwrap();
+        root
+    }
+
+    #[test]
+    fn finds_files_spelled_exactly() {
+        let root = temporary_root("assets_exact");
+        assert_eq!(
+            find_asset(root.path(), "fonts/Roboto-Thin.ttf"),
+            Lookup::Found
+        );
+    }
+
+    #[test]
+    fn catches_the_wrong_capitals() {
+        let root = temporary_root("assets_wrong_case");
+
+        assert_eq!(
+            find_asset(root.path(), "fonts/Roboto-thin.ttf"),
+            Lookup::WrongCase("fonts/Roboto-Thin.ttf".to_string())
+        );
+        assert_eq!(
+            find_asset(root.path(), "Fonts/Roboto-Thin.ttf"),
+            Lookup::WrongCase("fonts/Roboto-Thin.ttf".to_string())
+        );
+    }
+
+    #[test]
+    fn reports_missing_files_and_folders() {
+        let root = temporary_root("assets_missing");
+
+        assert_eq!(
+            find_asset(root.path(), "fonts/Roboto-Bold.ttf"),
+            Lookup::Missing
+        );
+        assert_eq!(
+            find_asset(root.path(), "sounds/bg_music.mp3"),
+            Lookup::Missing
+        );
+        // A folder isn't an asset
+        assert_eq!(find_asset(root.path(), "fonts"), Lookup::Missing);
+    }
+
+    #[test]
+    fn every_asset_in_the_repo_is_spelled_right() {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("assets");
+
+        for asset in GameAsset::ALL.iter() {
+            // The music isn't checked in, the game plays without it
+            if *asset == GameAsset::BackgroundMusic {
+                continue;
+            }
+
+            assert_eq!(
+                find_asset(&root, asset.path()),
+                Lookup::Found,
+                "{:?}",
+                asset
+            );
+        }
+    }
+}
@@ -0,0 +1,98 @@
+// COMMAND LINE CODE
+
+use std::env;
+use std::path::PathBuf;
+use std::process;
+
+use crate::logic::difficulty::DifficultyPreset;
+use crate::logic::player::ControlScheme;
+
+const USAGE: &str =
+    "Usage: bevy_block_game [--headless <ticks>] [--seed <number>] [--config <path>] [--name <name>]\n       [--replay <path>] [--controls <arcade|classic>]\n       [--difficulty <easy|normal|hard|insane>]";
+
+// Options passed to the game on the command line
+#[derive(Default)]
+pub struct Options {
+    // Run the game logic without a window for this many ticks
+    pub headless_ticks: Option<u32>,
+    // Seed for the game's random numbers, picked at random if missing
+    pub seed: Option<u64>,
+    // Where to load the game config from instead of the default path
+    pub config_path: Option<PathBuf>,
+    // Name to put on high scores
+    pub player_name: Option<String>,
+    // Play a recorded replay instead of reading the keyboard
+    pub replay_path: Option<PathBuf>,
+    // How the player moves
+    pub control_scheme: Option<ControlScheme>,
+    // The difficulty preset to start on
+    pub preset: Option<DifficultyPreset>,
+}
+
+impl Options {
+    pub fn from_args() -> Self {
+        match Options::parse(env::args().skip(1)) {
+            Ok(options) => options,
+            Err(message) => {
+                eprintln!("{}\n{}", message, USAGE);
+                process::exit(2);
+            }
+        }
+    }
+
+    fn parse<I: Iterator<Item = String>>(mut args: I) -> Result<Self, String> {
+        let mut options = Options::default();
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--headless" => {
+                    let ticks = next_value(&mut args, &arg)?;
+                    options.headless_ticks = Some(
+                        ticks
+                            .parse()
+                            .map_err(|_| format!("Invalid tick count '{}'.", ticks))?,
+                    );
+                }
+                "--seed" => {
+                    let seed = next_value(&mut args, &arg)?;
+                    options.seed = Some(
+                        seed.parse()
+                            .map_err(|_| format!("Invalid seed '{}'.", seed))?,
+                    );
+                }
+                "--config" => {
+                    options.config_path = Some(PathBuf::from(next_value(&mut args, &arg)?));
+                }
+                "--name" => {
+                    options.player_name = Some(next_value(&mut args, &arg)?);
+                }
+                "--replay" => {
+                    options.replay_path = Some(PathBuf::from(next_value(&mut args, &arg)?));
+                }
+                "--controls" => {
+                    let name = next_value(&mut args, &arg)?;
+                    options.control_scheme = Some(
+                        ControlScheme::from_name(&name)
+                            .ok_or_else(|| format!("Unknown control scheme '{}'.", name))?,
+                    );
+                }
+                "--difficulty" => {
+                    let name = next_value(&mut args, &arg)?;
+                    options.preset = Some(
+                        DifficultyPreset::from_name(&name)
+                            .ok_or_else(|| format!("Unknown difficulty '{}'.", name))?,
+                    );
+                }
+                _ => return Err(format!("Unknown argument '{}'.", arg)),
+            }
+        }
+
+        Ok(options)
+    }
+}
+
+// Get the value that follows a flag like `--headless 600`
+fn next_value<I: Iterator<Item = String>>(args: &mut I, flag: &str) -> Result<String, String> {
+    args.next()
+        .ok_or_else(|| format!("Missing value for '{}'.", flag))
+}
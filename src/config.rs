@@ -0,0 +1,755 @@
+// CONFIG CODE
+
+use serde::de::IgnoredAny;
+use serde::{Deserialize, Deserializer, Serialize};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::logic::blocks::{BlockTier, SaturatedSpawnPolicy};
+use crate::logic::difficulty::DifficultyPreset;
+use crate::logic::player::ControlScheme;
+
+pub const DEFAULT_CONFIG_PATH: &str = "assets/config.ron";
+
+// Everything a designer might want to tune without recompiling.
+// Loaded once at startup and added to the app as a resource.
+//
+// Any value left out of the file keeps its default.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GameConfig {
+    pub window_width: f32,
+    pub window_height: f32,
+
+    // blocks spawn large and break into two of the next size down when shot
+    pub large_block: BlockTierConfig,
+    pub medium_block: BlockTierConfig,
+    pub small_block: BlockTierConfig,
+    // degrees between each of the two pieces and the way the broken block was going
+    pub fragment_angle: f32,
+    // pixels taken off every side of a block's hitbox
+    pub block_hitbox_inset: f32,
+    // how often blocks spawn, how fast they go and which sizes come in as a run goes on
+    pub difficulty: DifficultyCurve,
+    // tweaks everything above to make it easier or harder, the command line wins over this
+    pub preset: DifficultyPreset,
+    // how many blocks are on the board when a run starts
+    pub starting_blocks: usize,
+    // the space between two spawn positions on the same edge
+    pub spawn_gap: i16,
+    // how far apart the spawn positions are from each other
+    pub spawn_spacing: i16,
+    // what happens once every spawn position has a block in play
+    pub saturated_spawn_policy: SaturatedSpawnPolicy,
+    // with SpeedUp, how much the block speed multiplier goes up each time a spawn is due
+    pub saturated_speed_up: f32,
+    // with SpeedUp, the most times their normal speed blocks can go
+    pub max_block_speed: f32,
+
+    pub player_size: (f32, f32),
+    // pixels taken off every side of the player's hitbox, makes near misses kinder
+    pub player_hitbox_inset: f32,
+    // in pixels per second
+    pub player_velocity: f32,
+    pub teleport_distance: f32,
+    // teleports that can be stored up, 1 makes it a plain cooldown
+    pub teleport_charges: u32,
+    // seconds for one teleport to charge, 0 never runs out
+    pub teleport_recharge: f32,
+    // seconds the player can't be hit for after a teleport, 0 turns it off
+    pub teleport_blink: f32,
+    // hits the player can take in a run, the last one ends it
+    pub lives: u32,
+    // seconds the player can't be hit for after losing a life
+    pub respawn_invulnerability: f32,
+    // seconds ahead the player's spawn point is checked for oncoming blocks
+    pub respawn_horizon: f32,
+
+    // how the player moves, the command line wins over this
+    pub control_scheme: ControlScheme,
+    // the classic controls turn this many degrees per second
+    pub ship_turn_speed: f32,
+    // in pixels per second squared
+    pub ship_thrust: f32,
+    // how much of its speed the ship loses every second
+    pub ship_drag: f32,
+    // in pixels per second
+    pub ship_max_speed: f32,
+
+    // how far a gamepad stick has to be pushed before it moves the player, from 0 to 1
+    pub gamepad_dead_zone: f32,
+
+    // in pixels per second
+    pub bullet_velocity: f32,
+    pub bullet_radius: f32,
+    // seconds a bullet flies before it disappears
+    pub bullet_lifetime: f32,
+    // seconds between shots while the fire key is held
+    pub fire_cooldown: f32,
+    // the most bullets that can be on screen at once
+    pub max_bullets: usize,
+
+    // points for every second survived, counted to the tick
+    pub survival_points: f32,
+    // a block that comes within this many pixels of the player and leaves without hitting them
+    pub near_miss_distance: f32,
+    pub near_miss_points: i32,
+
+    // seed for the game's random numbers, the command line wins over this
+    pub seed: Option<u64>,
+    // name to put on high scores, the command line wins over this
+    pub player_name: Option<String>,
+
+    // Old configs set one spawn rate for the whole run, it now becomes a difficulty
+    // curve with a single keyframe. Left out when saving so it doesn't change the hash.
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        deserialize_with = "deserialize_block_spawn_timestep"
+    )]
+    pub block_spawn_timestep: Option<f32>,
+}
+
+// Old configs wrote the number on its own, not wrapped in Some
+fn deserialize_block_spawn_timestep<'de, D>(deserializer: D) -> Result<Option<f32>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    f32::deserialize(deserializer).map(Some)
+}
+
+// Which keys the config file actually has, every value left out looks
+// the same as one set to its default once it is in the GameConfig
+#[derive(Deserialize)]
+#[serde(rename = "GameConfig")]
+struct WrittenKeys {
+    #[serde(default, deserialize_with = "deserialize_written")]
+    difficulty: bool,
+}
+
+fn deserialize_written<'de, D>(deserializer: D) -> Result<bool, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    IgnoredAny::deserialize(deserializer).map(|_| true)
+}
+
+impl Default for GameConfig {
+    fn default() -> Self {
+        GameConfig {
+            window_width: 1500.0,
+            window_height: 1200.0,
+            large_block: BlockTierConfig {
+                size: (40.0, 40.0),
+                velocity: 300.0,
+                points: 5,
+            },
+            medium_block: BlockTierConfig {
+                size: (28.0, 28.0),
+                velocity: 360.0,
+                points: 10,
+            },
+            small_block: BlockTierConfig {
+                size: (18.0, 18.0),
+                velocity: 420.0,
+                points: 20,
+            },
+            fragment_angle: 30.0,
+            block_hitbox_inset: 0.0,
+            difficulty: DifficultyCurve::default(),
+            preset: DifficultyPreset::Normal,
+            starting_blocks: 6,
+            spawn_gap: 45,
+            spawn_spacing: 90,
+            saturated_spawn_policy: SaturatedSpawnPolicy::SpeedUp,
+            saturated_speed_up: 0.05,
+            max_block_speed: 2.0,
+            player_size: (40.0, 40.0),
+            player_hitbox_inset: 0.0,
+            player_velocity: 300.0,
+            teleport_distance: 70.0,
+            teleport_charges: 2,
+            teleport_recharge: 1.5,
+            teleport_blink: 0.0,
+            lives: 3,
+            respawn_invulnerability: 3.0,
+            respawn_horizon: 2.0,
+            control_scheme: ControlScheme::Arcade,
+            ship_turn_speed: 270.0,
+            ship_thrust: 600.0,
+            ship_drag: 0.8,
+            ship_max_speed: 450.0,
+            gamepad_dead_zone: 0.2,
+            bullet_velocity: 700.0,
+            bullet_radius: 5.0,
+            bullet_lifetime: 1.0,
+            fire_cooldown: 0.25,
+            max_bullets: 5,
+            survival_points: 1.0,
+            near_miss_distance: 20.0,
+            near_miss_points: 2,
+            seed: None,
+            player_name: None,
+            block_spawn_timestep: None,
+        }
+    }
+}
+
+// Keyframes of how hard the game is, measured in seconds survived or points scored.
+// The spawn interval and block speed blend smoothly from one keyframe to the next,
+// the block limit and sizes change when a keyframe is reached.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DifficultyCurve {
+    pub progress: DifficultyProgress,
+    // in order of when they are reached, the first one is where every run starts
+    pub keyframes: Vec<DifficultyKeyframe>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DifficultyProgress {
+    // seconds since the run started
+    Time,
+    Score,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DifficultyKeyframe {
+    // in seconds or points, depending on the curve's progress
+    pub at: f32,
+    // seconds between each new block
+    pub spawn_interval: f32,
+    // seconds a new block flashes at its spawn position before it comes in
+    pub spawn_warning: f32,
+    // times the speed each size of block has on its own
+    pub block_speed: f32,
+    // no more blocks spawn while this many are in play, pieces included
+    pub max_blocks: usize,
+    // the sizes new blocks can spawn as, the starting blocks are always large
+    pub tiers: Vec<BlockTier>,
+}
+
+impl DifficultyKeyframe {
+    pub fn new(
+        at: f32,
+        spawn_interval: f32,
+        spawn_warning: f32,
+        block_speed: f32,
+        max_blocks: usize,
+        tiers: &[BlockTier],
+    ) -> Self {
+        DifficultyKeyframe {
+            at,
+            spawn_interval,
+            spawn_warning,
+            block_speed,
+            max_blocks,
+            tiers: tiers.to_vec(),
+        }
+    }
+}
+
+impl DifficultyCurve {
+    // The same difficulty for the whole run, spawning a large block every spawn_interval
+    // seconds. This is how the game played before there were difficulty curves, so there
+    // is no spawn warning and no block limit other than running out of spawn positions.
+    pub fn constant(spawn_interval: f32) -> Self {
+        DifficultyCurve {
+            progress: DifficultyProgress::Time,
+            keyframes: vec![DifficultyKeyframe::new(
+                0.0,
+                spawn_interval,
+                0.0,
+                1.0,
+                usize::MAX,
+                &[BlockTier::Large],
+            )],
+        }
+    }
+}
+
+impl Default for DifficultyCurve {
+    fn default() -> Self {
+        use BlockTier::{Large, Medium, Small};
+
+        let keyframe = DifficultyKeyframe::new;
+
+        DifficultyCurve {
+            progress: DifficultyProgress::Time,
+            keyframes: vec![
+                keyframe(0.0, 2.0, 1.0, 1.0, 12, &[Large]),
+                keyframe(45.0, 1.6, 0.8, 1.1, 16, &[Large, Medium]),
+                keyframe(90.0, 1.2, 0.6, 1.2, 22, &[Large, Medium]),
+                keyframe(180.0, 0.8, 0.4, 1.35, 40, &[Large, Medium, Small]),
+            ],
+        }
+    }
+}
+
+// How one size of block looks and plays
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BlockTierConfig {
+    pub size: (f32, f32),
+    // in pixels per second
+    pub velocity: f32,
+    // points for shooting one of these
+    pub points: i32,
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(io::Error),
+    Parse(ron::Error),
+    Invalid(Vec<String>),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::Io(error) => write!(f, "Could not read the config file: {}", error),
+            ConfigError::Parse(error) => write!(f, "Could not parse the config file: {}", error),
+            ConfigError::Invalid(problems) => {
+                write!(f, "The config file has bad values:")?;
+                for problem in problems {
+                    write!(f, "\n  - {}", problem)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl GameConfig {
+    // A missing file is fine, the game just uses the defaults.
+    // A file that can't be read or has bad values is an error.
+    pub fn load(path: &Path) -> Result<Self, ConfigError> {
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => {
+                println!("No config at {}, using the defaults.", path.display());
+                return Ok(GameConfig::default());
+            }
+            Err(error) => return Err(ConfigError::Io(error)),
+        };
+
+        GameConfig::parse(&contents)
+    }
+
+    fn parse(contents: &str) -> Result<Self, ConfigError> {
+        let mut config: GameConfig = ron::de::from_str(contents).map_err(ConfigError::Parse)?;
+
+        if let Some(block_spawn_timestep) = config.block_spawn_timestep.take() {
+            // Otherwise one of the two would be quietly ignored
+            let written: WrittenKeys = ron::de::from_str(contents).map_err(ConfigError::Parse)?;
+            if written.difficulty {
+                return Err(ConfigError::Invalid(vec![
+                    "block_spawn_timestep and difficulty can't both be set, replace block_spawn_timestep with a difficulty keyframe.".to_string(),
+                ]));
+            }
+
+            println!(
+                "block_spawn_timestep is deprecated, using a difficulty curve that spawns a block every {} seconds instead. Replace it with difficulty to change this.",
+                block_spawn_timestep
+            );
+
+            if !block_spawn_timestep.is_finite() || block_spawn_timestep <= 0.0 {
+                return Err(ConfigError::Invalid(vec![format!(
+                    "block_spawn_timestep must be above 0, got {}.",
+                    block_spawn_timestep
+                )]));
+            }
+
+            config.difficulty = DifficultyCurve::constant(block_spawn_timestep);
+        }
+
+        config.validate()?;
+
+        Ok(config)
+    }
+
+    fn validate(&self) -> Result<(), ConfigError> {
+        let mut problems = Vec::new();
+
+        let positive = [
+            ("window_width", self.window_width),
+            ("window_height", self.window_height),
+            ("large_block.size.0", self.large_block.size.0),
+            ("large_block.size.1", self.large_block.size.1),
+            ("medium_block.size.0", self.medium_block.size.0),
+            ("medium_block.size.1", self.medium_block.size.1),
+            ("small_block.size.0", self.small_block.size.0),
+            ("small_block.size.1", self.small_block.size.1),
+            ("player_size.0", self.player_size.0),
+            ("player_size.1", self.player_size.1),
+            ("bullet_radius", self.bullet_radius),
+            ("bullet_lifetime", self.bullet_lifetime),
+        ];
+
+        for (name, value) in positive.iter() {
+            if !value.is_finite() || *value <= 0.0 {
+                problems.push(format!("{} must be above 0, got {}.", name, value));
+            }
+        }
+
+        let not_negative = [
+            ("large_block.velocity", self.large_block.velocity),
+            ("medium_block.velocity", self.medium_block.velocity),
+            ("small_block.velocity", self.small_block.velocity),
+            ("player_velocity", self.player_velocity),
+            ("teleport_distance", self.teleport_distance),
+            ("teleport_recharge", self.teleport_recharge),
+            ("teleport_blink", self.teleport_blink),
+            ("bullet_velocity", self.bullet_velocity),
+            ("fire_cooldown", self.fire_cooldown),
+            ("ship_turn_speed", self.ship_turn_speed),
+            ("ship_thrust", self.ship_thrust),
+            ("ship_drag", self.ship_drag),
+            ("ship_max_speed", self.ship_max_speed),
+            ("block_hitbox_inset", self.block_hitbox_inset),
+            ("player_hitbox_inset", self.player_hitbox_inset),
+            ("respawn_invulnerability", self.respawn_invulnerability),
+            ("respawn_horizon", self.respawn_horizon),
+            ("saturated_speed_up", self.saturated_speed_up),
+            ("survival_points", self.survival_points),
+            ("near_miss_distance", self.near_miss_distance),
+        ];
+
+        for (name, value) in not_negative.iter() {
+            if !value.is_finite() || *value < 0.0 {
+                problems.push(format!("{} can't be negative, got {}.", name, value));
+            }
+        }
+
+        // An inset this big would leave nothing to hit
+        // Every size of block shares the same inset
+        let smallest_block_side = self
+            .block_tiers()
+            .iter()
+            .map(|tier| tier.size.0.min(tier.size.1))
+            .fold(f32::INFINITY, f32::min);
+
+        let hitboxes = [
+            (
+                "block_hitbox_inset",
+                self.block_hitbox_inset,
+                smallest_block_side,
+            ),
+            (
+                "player_hitbox_inset",
+                self.player_hitbox_inset,
+                self.player_size.0.min(self.player_size.1),
+            ),
+        ];
+
+        for (name, inset, smallest_side) in hitboxes.iter() {
+            if *inset * 2.0 >= *smallest_side {
+                problems.push(format!(
+                    "{} must be less than half the smallest side, got {}.",
+                    name, inset
+                ));
+            }
+        }
+
+        // The spawn grid finds the edge of the screen by turning the window size into an i16
+        if self.window_width > i16::MAX as f32 || self.window_height > i16::MAX as f32 {
+            problems.push(format!(
+                "The window can be at most {} pixels wide or high.",
+                i16::MAX
+            ));
+        }
+
+        if !(0.0..1.0).contains(&self.gamepad_dead_zone) {
+            problems.push(format!(
+                "gamepad_dead_zone must be at least 0 and below 1, got {}.",
+                self.gamepad_dead_zone
+            ));
+        }
+
+        // Blocks never start out faster than this
+        if !self.max_block_speed.is_finite() || self.max_block_speed < 1.0 {
+            problems.push(format!(
+                "max_block_speed must be at least 1, got {}.",
+                self.max_block_speed
+            ));
+        }
+
+        if self.lives == 0 {
+            problems.push("lives must be at least 1.".to_string());
+        }
+
+        if self.teleport_charges == 0 {
+            problems.push("teleport_charges must be at least 1.".to_string());
+        }
+
+        problems.extend(self.difficulty.problems());
+
+        if self.spawn_gap < 0 {
+            problems.push(format!(
+                "spawn_gap can't be negative, got {}.",
+                self.spawn_gap
+            ));
+        }
+
+        // The number of spawn positions per edge is the window size divided by this
+        let large_size = self.large_block.size;
+        if self.spawn_gap as f32 + large_size.0.min(large_size.1) < 1.0 {
+            problems
+                .push("spawn_gap plus the large block size must be at least 1 pixel.".to_string());
+        }
+
+        if self.spawn_spacing <= 0 {
+            problems.push(format!(
+                "spawn_spacing must be above 0, got {}.",
+                self.spawn_spacing
+            ));
+        }
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(ConfigError::Invalid(problems))
+        }
+    }
+
+    // A fingerprint of every value that changes how a run plays out.
+    // Replays store it so we can tell when one is played with a different config.
+    // The control scheme and preset are left out because replays store them for each run,
+    // and the dead zone because replays store the stick after it.
+    //
+    // NOTE
+    // This is FNV-1a over the config written out as RON, which unlike
+    // the std hasher is guaranteed to stay the same between builds.
+    pub fn gameplay_hash(&self) -> u64 {
+        let gameplay = GameConfig {
+            seed: None,
+            player_name: None,
+            control_scheme: ControlScheme::default(),
+            preset: DifficultyPreset::default(),
+            gamepad_dead_zone: 0.0,
+            ..self.clone()
+        };
+
+        let contents = ron::ser::to_string(&gameplay).expect("The config is plain data.");
+
+        contents.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+            (hash ^ byte as u64).wrapping_mul(0x0000_0100_0000_01b3)
+        })
+    }
+
+    // Where every run starts
+    pub fn starting_difficulty(&self) -> &DifficultyKeyframe {
+        self.difficulty
+            .keyframes
+            .first()
+            .expect("The config is validated to have at least one keyframe.")
+    }
+
+    pub fn block_tiers(&self) -> [&BlockTierConfig; 3] {
+        [&self.large_block, &self.medium_block, &self.small_block]
+    }
+
+    // The edge of the screen is half of the total size
+    pub fn half_width(&self) -> f32 {
+        self.window_width / 2.0
+    }
+
+    pub fn half_height(&self) -> f32 {
+        self.window_height / 2.0
+    }
+}
+
+impl DifficultyCurve {
+    fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+
+        if self.keyframes.is_empty() {
+            problems.push("difficulty needs at least one keyframe.".to_string());
+        }
+
+        for (index, keyframe) in self.keyframes.iter().enumerate() {
+            let name = format!("difficulty.keyframes[{}]", index);
+
+            if !keyframe.at.is_finite() || keyframe.at < 0.0 {
+                problems.push(format!(
+                    "{}.at can't be negative, got {}.",
+                    name, keyframe.at
+                ));
+            }
+
+            if !keyframe.spawn_interval.is_finite() || keyframe.spawn_interval <= 0.0 {
+                problems.push(format!(
+                    "{}.spawn_interval must be above 0, got {}.",
+                    name, keyframe.spawn_interval
+                ));
+            }
+
+            if !keyframe.spawn_warning.is_finite() || keyframe.spawn_warning < 0.0 {
+                problems.push(format!(
+                    "{}.spawn_warning can't be negative, got {}.",
+                    name, keyframe.spawn_warning
+                ));
+            }
+
+            if !keyframe.block_speed.is_finite() || keyframe.block_speed < 0.0 {
+                problems.push(format!(
+                    "{}.block_speed can't be negative, got {}.",
+                    name, keyframe.block_speed
+                ));
+            }
+
+            if keyframe.max_blocks == 0 {
+                problems.push(format!("{}.max_blocks must be above 0, got 0.", name));
+            }
+
+            if keyframe.tiers.is_empty() {
+                problems.push(format!("{}.tiers needs at least one block size.", name));
+            }
+        }
+
+        // Otherwise there is no telling which keyframe comes next
+        if self
+            .keyframes
+            .windows(2)
+            .any(|pair| pair[1].at <= pair[0].at)
+        {
+            problems.push(
+                "difficulty.keyframes must be in order of when they are reached.".to_string(),
+            );
+        }
+
+        problems
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn problems(contents: &str) -> Vec<String> {
+        match GameConfig::parse(contents) {
+            Err(ConfigError::Invalid(problems)) => problems,
+            Err(error) => panic!("{}", error),
+            Ok(_config) => Vec::new(),
+        }
+    }
+
+    #[test]
+    fn the_shipped_config_is_valid() {
+        let contents = fs::read_to_string(DEFAULT_CONFIG_PATH).unwrap();
+        assert!(problems(&contents).is_empty());
+    }
+
+    fn validate(config: GameConfig) -> Vec<String> {
+        match config.validate() {
+            Err(ConfigError::Invalid(problems)) => problems,
+            Err(error) => panic!("{}", error),
+            Ok(()) => Vec::new(),
+        }
+    }
+
+    #[test]
+    fn sizes_must_be_positive_numbers() {
+        let problems = validate(GameConfig {
+            window_width: -1.0,
+            player_size: (40.0, f32::NAN),
+            large_block: BlockTierConfig {
+                size: (0.0, 40.0),
+                ..GameConfig::default().large_block
+            },
+            ..GameConfig::default()
+        });
+
+        for name in ["window_width", "large_block.size.0", "player_size.1"].iter() {
+            let expected = format!("{} must be above 0", name);
+            assert!(problems
+                .iter()
+                .any(|problem| problem.starts_with(&expected)));
+        }
+    }
+
+    #[test]
+    fn spawn_grid_needs_a_gap_between_positions() {
+        let problems = validate(GameConfig {
+            spawn_spacing: 0,
+            ..GameConfig::default()
+        });
+        assert_eq!(problems, vec!["spawn_spacing must be above 0, got 0."]);
+
+        let problems = validate(GameConfig {
+            spawn_gap: 0,
+            large_block: BlockTierConfig {
+                size: (0.5, 0.5),
+                ..GameConfig::default().large_block
+            },
+            ..GameConfig::default()
+        });
+        assert_eq!(
+            problems,
+            vec!["spawn_gap plus the large block size must be at least 1 pixel."]
+        );
+
+        // No gap is fine as long as the blocks themselves are a pixel wide
+        assert!(validate(GameConfig {
+            spawn_gap: 0,
+            ..GameConfig::default()
+        })
+        .is_empty());
+    }
+
+    #[test]
+    fn window_has_to_fit_the_spawn_grid() {
+        let biggest = GameConfig {
+            window_width: i16::MAX as f32,
+            window_height: i16::MAX as f32,
+            ..GameConfig::default()
+        };
+        assert!(validate(biggest).is_empty());
+
+        let problems = validate(GameConfig {
+            window_height: i16::MAX as f32 + 1.0,
+            ..GameConfig::default()
+        });
+        assert_eq!(
+            problems,
+            vec!["The window can be at most 32767 pixels wide or high."]
+        );
+    }
+
+    #[test]
+    fn legacy_spawn_timestep_plays_like_it_used_to() {
+        let config = GameConfig::parse("(block_spawn_timestep: 1.5, lives: 2)").unwrap();
+
+        assert_eq!(config.lives, 2);
+        assert!(config.block_spawn_timestep.is_none());
+        assert_eq!(config.difficulty.progress, DifficultyProgress::Time);
+        assert_eq!(config.difficulty.keyframes.len(), 1);
+
+        let start = config.starting_difficulty();
+        assert_eq!(start.spawn_interval, 1.5);
+        assert_eq!(start.spawn_warning, 0.0);
+        assert_eq!(start.block_speed, 1.0);
+        assert_eq!(start.max_blocks, usize::MAX);
+        assert_eq!(start.tiers, vec![BlockTier::Large]);
+    }
+
+    #[test]
+    fn legacy_spawn_timestep_and_difficulty_cant_both_be_set() {
+        let contents = "GameConfig(
+            block_spawn_timestep: 1.5,
+            difficulty: (progress: Time, keyframes: [
+                (at: 0.0, spawn_interval: 1.0, spawn_warning: 0.5, block_speed: 1.0, max_blocks: 10, tiers: [Large]),
+            ]),
+        )";
+
+        assert_eq!(problems(contents).len(), 1);
+        assert!(problems(contents)[0].contains("can't both be set"));
+    }
+
+    #[test]
+    fn legacy_spawn_timestep_must_be_positive() {
+        assert_eq!(problems("(block_spawn_timestep: 0.0)").len(), 1);
+    }
+}
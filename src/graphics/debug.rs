@@ -0,0 +1,89 @@
+// DEBUG CODE
+
+use bevy::prelude::*;
+
+use crate::assets::GameAssets;
+use crate::logic::actions::{Action, InputActions};
+use crate::logic::blocks::{Block, BlockSpeed};
+use crate::logic::difficulty::Difficulty;
+
+pub struct DebugPlugin;
+
+impl Plugin for DebugPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_startup_system(render_debug_text.system())
+            .add_system(toggle_debug_text.system())
+            .add_system(debug_text_system.system());
+    }
+}
+
+// Marks the text with the difficulty readout, hidden until it is toggled on
+struct DebugText;
+
+fn render_debug_text(mut commands: Commands, game_assets: Res<GameAssets>) {
+    let style = Style {
+        position_type: PositionType::Absolute,
+        position: Rect {
+            top: Val::Px(20.0),
+            left: Val::Px(20.0),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+
+    commands
+        .spawn_bundle(TextBundle {
+            style,
+            text: Text::with_section(
+                "",
+                TextStyle {
+                    font: game_assets.fallback_font(),
+                    font_size: 20.0,
+                    color: Color::rgb(0.6, 0.9, 0.6),
+                },
+                Default::default(),
+            ),
+            visible: Visible {
+                is_visible: false,
+                is_transparent: true,
+            },
+            ..Default::default()
+        })
+        .insert(DebugText);
+}
+
+fn toggle_debug_text(
+    input_actions: Res<InputActions>,
+    mut text_query: Query<&mut Visible, With<DebugText>>,
+) {
+    if !input_actions.just_pressed(Action::ToggleDebug) {
+        return;
+    }
+
+    for mut visible in text_query.iter_mut() {
+        visible.is_visible = !visible.is_visible;
+    }
+}
+
+// Only filled in while it is showing
+fn debug_text_system(
+    difficulty: Res<Difficulty>,
+    block_speed: Res<BlockSpeed>,
+    block_query: Query<(), With<Block>>,
+    mut text_query: Query<(&mut Text, &Visible), With<DebugText>>,
+) {
+    for (mut text, visible) in text_query.iter_mut() {
+        if !visible.is_visible {
+            continue;
+        }
+
+        text.sections[0].value = format!(
+            "Difficulty level {} at {:.1}\n{}\n{} blocks in play, {:.2} times faster once the board filled up",
+            difficulty.level,
+            difficulty.progress,
+            difficulty.summary(),
+            block_query.iter().count(),
+            block_speed.0
+        );
+    }
+}
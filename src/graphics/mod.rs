@@ -1,3 +1,21 @@
+use bevy::app::{PluginGroup, PluginGroupBuilder};
+
 /// Block Game Graphics
+pub mod debug;
 pub mod score;
+pub mod sprites;
 pub mod text;
+
+// Draws the game. These plugins need a window, so they
+// are only added on top of DefaultPlugins.
+pub struct GraphicsPlugins;
+
+impl PluginGroup for GraphicsPlugins {
+    fn build(&mut self, group: &mut PluginGroupBuilder) {
+        group
+            .add(sprites::SpritesPlugin)
+            .add(score::ScorePlugin)
+            .add(text::TextPlugin)
+            .add(debug::DebugPlugin);
+    }
+}
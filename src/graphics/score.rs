@@ -1,62 +1,67 @@
 // SCORE CODE
 
-use bevy::asset::AssetServer;
-use bevy::core::FixedTimestep;
 use bevy::prelude::*;
 
-use crate::logic::player::PlayerDeathEvent;
-
-// For SCORE_ACC_TIMESTEP, it's once every two seconds
-const SCORE_ACC_TIMESTEP: f64 = 1.0;
+use crate::assets::GameAssets;
+use crate::config::GameConfig;
+use crate::logic::difficulty::DifficultyPreset;
+use crate::logic::high_scores::HighScores;
+use crate::logic::player::Lives;
+use crate::logic::score::Score;
+use crate::logic::teleport::Teleport;
 
 pub struct ScorePlugin;
 
 impl Plugin for ScorePlugin {
     fn build(&self, app: &mut AppBuilder) {
         app.add_startup_system(render_score.system())
-            .add_system(stop_score_counter.system())
-            .add_system_set(
-                SystemSet::new()
-                    .with_run_criteria(FixedTimestep::step(SCORE_ACC_TIMESTEP))
-                    .with_system(score_update_system.system()),
-            );
+            .add_startup_system(render_lives.system())
+            .add_startup_system(render_teleport_meter.system())
+            .add_system(score_text_system.system())
+            .add_system(lives_text_system.system())
+            .add_system(teleport_meter_system.system());
     }
 }
 
-pub struct Score {
-    pub value: i32,
-    pub active: bool,
-}
+// Marks the text that shows the live score
+struct ScoreText;
 
-fn stop_score_counter(
-    mut reset_game_event: EventReader<PlayerDeathEvent>,
-    mut score_query: Query<&mut Score>,
-) {
-    for _event in reset_game_event.iter() {
-        // Stop accumulating the score
-        let mut score = score_query
-            .single_mut()
-            .expect("There should only be one score in the game.");
+// Marks the text under the score that shows the lives left
+struct LivesText;
 
-        score.active = false;
-    }
-}
+// Marks the text under the lives that shows the teleport charge
+struct TeleportMeter;
+
+// Characters in the bar for the teleport filling up
+const METER_WIDTH: usize = 10;
 
 // NOTE
 // I have no clue what a lot of the styling/positions does here.
 // Will need to come back to this at some point.
-fn render_score(mut commands: Commands, asset_server: Res<AssetServer>) {
+fn render_score(mut commands: Commands, game_assets: Res<GameAssets>) {
+    let font = game_assets.font();
+
     let text_section = TextSection {
         value: 0.to_string(),
         style: TextStyle {
-            font: asset_server.load("fonts/Roboto-Thin.ttf"),
+            font: font.clone(),
             font_size: 60.0,
             color: Color::rgb(0.9, 0.9, 0.9),
         },
     };
 
+    // Filled in by the score text system
+    let best_section = TextSection {
+        value: String::new(),
+        style: TextStyle {
+            font,
+            font_size: 30.0,
+            color: Color::rgb(0.6, 0.6, 0.6),
+        },
+    };
+
     let text = Text {
-        sections: vec![text_section],
+        sections: vec![text_section, best_section],
         alignment: TextAlignment {
             vertical: VerticalAlign::Center,
             horizontal: HorizontalAlign::Center,
@@ -82,21 +87,135 @@ fn render_score(mut commands: Commands, asset_server: Res<AssetServer>) {
             text,
             ..Default::default()
         })
-        .insert(Score {
-            value: 0,
-            active: true,
-        });
+        .insert(ScoreText);
+}
+
+fn render_lives(mut commands: Commands, game_assets: Res<GameAssets>) {
+    let style = Style {
+        position_type: PositionType::Absolute,
+        position: Rect {
+            top: Val::Px(110.0),
+            right: Val::Px(80.0),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+
+    commands
+        .spawn_bundle(TextBundle {
+            style,
+            // Filled in by the lives text system
+            text: Text::with_section(
+                "",
+                TextStyle {
+                    font: game_assets.font(),
+                    font_size: 30.0,
+                    color: Color::rgb(0.9, 0.9, 0.9),
+                },
+                Default::default(),
+            ),
+            ..Default::default()
+        })
+        .insert(LivesText);
+}
+
+fn lives_text_system(lives: Res<Lives>, mut text_query: Query<&mut Text, With<LivesText>>) {
+    if !lives.is_changed() {
+        return;
+    }
+
+    for mut text in text_query.iter_mut() {
+        text.sections[0].value = match lives.0 {
+            1 => "1 life left".to_string(),
+            lives => format!("{} lives left", lives),
+        };
+    }
+}
+
+fn render_teleport_meter(mut commands: Commands, game_assets: Res<GameAssets>) {
+    let style = Style {
+        position_type: PositionType::Absolute,
+        position: Rect {
+            top: Val::Px(145.0),
+            right: Val::Px(80.0),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+
+    commands
+        .spawn_bundle(TextBundle {
+            style,
+            // Filled in by the teleport meter system
+            text: Text::with_section(
+                "",
+                TextStyle {
+                    font: game_assets.font(),
+                    font_size: 30.0,
+                    color: Color::rgb(0.5, 0.8, 1.0),
+                },
+                Default::default(),
+            ),
+            ..Default::default()
+        })
+        .insert(TeleportMeter);
 }
 
-fn score_update_system(mut score_query: Query<(&mut Score, &mut Text)>) {
-    let (mut score, mut text) = score_query
+// Shows the stored teleports and a bar for the one charging,
+// the bar is full once every teleport is stored
+fn teleport_meter_system(
+    teleport: Res<Teleport>,
+    config: Res<GameConfig>,
+    mut text_query: Query<&mut Text, With<TeleportMeter>>,
+) {
+    if !teleport.is_changed() {
+        return;
+    }
+
+    let stored = teleport.charge.floor();
+    let filling = if stored >= config.teleport_charges as f32 {
+        1.0
+    } else {
+        teleport.charge - stored
+    };
+    let filled = (filling * METER_WIDTH as f32) as usize;
+
+    for mut text in text_query.iter_mut() {
+        text.sections[0].value = format!(
+            "teleports {} [{}{}]",
+            stored,
+            "#".repeat(filled),
+            "-".repeat(METER_WIDTH - filled)
+        );
+    }
+}
+
+// Keep the score text in sync with the score and high score resources.
+// The best score shown is the one for the preset being played.
+//
+// NOTE
+// Watching a replay leaves the high scores out,
+// so there is no best score line then.
+fn score_text_system(
+    score: Res<Score>,
+    high_scores: Option<Res<HighScores>>,
+    preset: Res<DifficultyPreset>,
+    mut text_query: Query<&mut Text, With<ScoreText>>,
+) {
+    let high_scores_changed = high_scores
+        .as_ref()
+        .map_or(false, |high_scores| high_scores.is_changed());
+    if !score.is_changed() && !high_scores_changed && !preset.is_changed() {
+        return;
+    }
+
+    let mut text = text_query
         .single_mut()
         .expect("There should only be one score in the game.");
 
-    // accumulate the score if its active
-    if score.active {
-        score.value += 1;
-        let string_score: String = score.value.to_string();
-        text.sections[0].value = string_score;
-    }
+    text.sections[0].value = score.value.to_string();
+    text.sections[1].value = match high_scores.and_then(|high_scores| high_scores.best(*preset)) {
+        Some(best) => format!("  best {} on {}", best, preset.name()),
+        None => String::new(),
+    };
 }
@@ -0,0 +1,312 @@
+// SPRITES CODE
+
+use bevy::prelude::*;
+
+use crate::assets::{GameAsset, GameAssets};
+use crate::config::GameConfig;
+use crate::logic::blocks::{Block, BlockTier, PooledBlock, SpawnWarning};
+use crate::logic::player::{Invulnerable, Player, PlayerDeathEvent};
+use crate::logic::projectiles::Bullet;
+use crate::logic::respawn::Respawning;
+use crate::logic::state::GameState;
+use crate::logic::teleport::Teleport;
+use crate::Dimensions;
+
+pub struct SpritesPlugin;
+
+impl Plugin for SpritesPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        // NOTE
+        // These run after the update stage so the transform they copy
+        // into the sprite bundle already has this frame's movement in it.
+        app.init_resource::<SpriteMaterials>()
+            .add_system_set(
+                SystemSet::on_exit(GameState::Loading)
+                    .with_system(refresh_sprite_materials.system()),
+            )
+            .add_system_to_stage(CoreStage::PostUpdate, add_block_sprites.system())
+            .add_system_to_stage(CoreStage::PostUpdate, hide_pooled_blocks.system())
+            .add_system_to_stage(CoreStage::PostUpdate, flash_spawn_warnings.system())
+            .add_system_to_stage(CoreStage::PostUpdate, mark_killer_block.system())
+            .add_system_to_stage(CoreStage::PostUpdate, add_player_sprite.system())
+            .add_system_to_stage(CoreStage::PostUpdate, blink_invulnerable_player.system())
+            .add_system_to_stage(CoreStage::PostUpdate, add_bullet_sprites.system())
+            .add_startup_system(spawn_teleport_preview.system())
+            .add_system_to_stage(CoreStage::PostUpdate, move_teleport_preview.system());
+    }
+}
+
+// One material for each kind of sprite, shared by everything that looks the same.
+// Made once when the game starts, so spawning never adds to the material assets.
+struct SpriteMaterials {
+    large_block: Handle<ColorMaterial>,
+    medium_block: Handle<ColorMaterial>,
+    small_block: Handle<ColorMaterial>,
+    player: Handle<ColorMaterial>,
+    bullet: Handle<ColorMaterial>,
+    spawn_warning: Handle<ColorMaterial>,
+    teleport_preview: Handle<ColorMaterial>,
+    killer_block: Handle<ColorMaterial>,
+}
+
+// Marks the outline showing where a teleport would land
+struct TeleportPreview;
+
+// Seconds a spawn warning spends on, then off
+const WARNING_FLASH: f32 = 0.15;
+// The same for the player while they can't be hit
+const INVULNERABLE_BLINK: f32 = 0.1;
+
+// Each block size has its own look
+const BLOCK_LOOKS: [(BlockTier, GameAsset, Color); 3] = [
+    (BlockTier::Large, GameAsset::LargeBlockTexture, Color::WHITE),
+    (
+        BlockTier::Medium,
+        GameAsset::MediumBlockTexture,
+        Color::WHITE,
+    ),
+    // There are only two block textures, so the smallest blocks are a lighter shade
+    (
+        BlockTier::Small,
+        GameAsset::MediumBlockTexture,
+        Color::rgb(1.0, 0.8, 0.8),
+    ),
+];
+
+impl FromWorld for SpriteMaterials {
+    fn from_world(world: &mut World) -> Self {
+        let world = world.cell();
+        let game_assets = world
+            .get_resource::<GameAssets>()
+            .expect("The assets plugin is added before the graphics plugins.");
+        let mut materials = world
+            .get_resource_mut::<Assets<ColorMaterial>>()
+            .expect("The sprite plugin is added before the graphics plugins.");
+
+        let mut block_material = |index: usize| {
+            let (_tier, asset, tint) = BLOCK_LOOKS[index];
+            materials.add(game_assets.material(asset, tint))
+        };
+
+        SpriteMaterials {
+            large_block: block_material(0),
+            medium_block: block_material(1),
+            small_block: block_material(2),
+            player: materials.add(game_assets.material(GameAsset::PlayerTexture, Color::WHITE)),
+            // Bullets don't have a texture, they are plain squares of color
+            bullet: materials.add(Color::rgb(1.0, 0.9, 0.5).into()),
+            spawn_warning: materials.add(Color::rgba(1.0, 0.3, 0.2, 0.6).into()),
+            teleport_preview: materials.add(Color::rgba(0.5, 0.8, 1.0, 0.3).into()),
+            killer_block: materials.add(Color::rgb(0.9, 0.2, 0.2).into()),
+        }
+    }
+}
+
+impl SpriteMaterials {
+    fn block(&self, tier: BlockTier) -> Handle<ColorMaterial> {
+        match tier {
+            BlockTier::Large => self.large_block.clone(),
+            BlockTier::Medium => self.medium_block.clone(),
+            BlockTier::Small => self.small_block.clone(),
+        }
+    }
+}
+
+// A texture that failed to load is only known once loading is over,
+// so swap in its fallback without making a new material
+fn refresh_sprite_materials(
+    sprite_materials: Res<SpriteMaterials>,
+    game_assets: Res<GameAssets>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+) {
+    for (tier, asset, tint) in BLOCK_LOOKS.iter() {
+        if let Some(material) = materials.get_mut(sprite_materials.block(*tier)) {
+            *material = game_assets.material(*asset, *tint);
+        }
+    }
+
+    if let Some(material) = materials.get_mut(sprite_materials.player.clone()) {
+        *material = game_assets.material(GameAsset::PlayerTexture, Color::WHITE);
+    }
+}
+
+// Give blocks something to draw when they come into play, and warnings something to flash.
+// Blocks from the pool or at the end of their warning already have a sprite,
+// it only needs showing again with the right look.
+#[allow(clippy::type_complexity)]
+fn add_block_sprites(
+    mut commands: Commands,
+    sprite_materials: Res<SpriteMaterials>,
+    mut block_query: Query<
+        (
+            Entity,
+            Option<&Block>,
+            &Transform,
+            &Dimensions,
+            Option<(
+                &mut Handle<ColorMaterial>,
+                &mut Sprite,
+                &mut Visible,
+                &mut GlobalTransform,
+            )>,
+        ),
+        Or<(Added<Block>, Added<SpawnWarning>)>,
+    >,
+) {
+    for (entity, block, transform, dimensions, sprite) in block_query.iter_mut() {
+        let material = match block {
+            Some(block) => sprite_materials.block(block.tier),
+            None => sprite_materials.spawn_warning.clone(),
+        };
+
+        match sprite {
+            Some((mut material_handle, mut sprite, mut visible, mut global_transform)) => {
+                *material_handle = material;
+                sprite.size = dimensions.0;
+                visible.is_visible = true;
+                // See sprite_bundle about setting this up front
+                *global_transform = GlobalTransform::from(*transform);
+            }
+            None => {
+                commands
+                    .entity(entity)
+                    .insert_bundle(sprite_bundle(material, transform, dimensions));
+            }
+        }
+    }
+}
+
+fn hide_pooled_blocks(mut pooled_query: Query<&mut Visible, Added<PooledBlock>>) {
+    for mut visible in pooled_query.iter_mut() {
+        visible.is_visible = false;
+    }
+}
+
+fn flash_spawn_warnings(mut warning_query: Query<(&SpawnWarning, &mut Visible)>) {
+    for (warning, mut visible) in warning_query.iter_mut() {
+        let flashes = (warning.timer.elapsed_secs() / WARNING_FLASH) as u32;
+        visible.is_visible = flashes % 2 == 0;
+    }
+}
+
+// The block that ended the run stays red on the game over screen.
+// It gets its own look back when the next run takes it from the pool.
+fn mark_killer_block(
+    mut player_death_event: EventReader<PlayerDeathEvent>,
+    sprite_materials: Res<SpriteMaterials>,
+    mut material_query: Query<&mut Handle<ColorMaterial>, With<Block>>,
+) {
+    for death in player_death_event.iter() {
+        if let Ok(mut material) = material_query.get_mut(death.block) {
+            *material = sprite_materials.killer_block.clone();
+        }
+    }
+}
+
+fn add_player_sprite(
+    mut commands: Commands,
+    sprite_materials: Res<SpriteMaterials>,
+    player_query: Query<(Entity, &Transform, &Dimensions, Option<&Respawning>), Added<Player>>,
+) {
+    for (entity, transform, dimensions, respawning) in player_query.iter() {
+        let mut sprite = sprite_bundle(sprite_materials.player.clone(), transform, dimensions);
+        // See blink_invulnerable_player, which can't see the sprite until next frame
+        sprite.visible.is_visible = respawning.is_none();
+
+        commands.entity(entity).insert_bundle(sprite);
+    }
+}
+
+// Respawning players are hidden until they have been moved somewhere safe
+#[allow(clippy::type_complexity)]
+fn blink_invulnerable_player(
+    mut player_query: Query<
+        (Option<&Invulnerable>, Option<&Respawning>, &mut Visible),
+        With<Player>,
+    >,
+) {
+    for (invulnerable, respawning, mut visible) in player_query.iter_mut() {
+        visible.is_visible = match (invulnerable, respawning) {
+            (_, Some(_)) => false,
+            (Some(invulnerable), None) => {
+                let blinks = (invulnerable.0.elapsed_secs() / INVULNERABLE_BLINK) as u32;
+                blinks % 2 == 0
+            }
+            (None, None) => true,
+        };
+    }
+}
+
+fn spawn_teleport_preview(
+    mut commands: Commands,
+    sprite_materials: Res<SpriteMaterials>,
+    config: Res<GameConfig>,
+) {
+    let size = Vec2::new(config.player_size.0, config.player_size.1);
+
+    commands
+        .spawn_bundle(SpriteBundle {
+            sprite: Sprite::new(size),
+            material: sprite_materials.teleport_preview.clone(),
+            visible: Visible {
+                is_visible: false,
+                is_transparent: true,
+            },
+            ..Default::default()
+        })
+        .insert(TeleportPreview);
+}
+
+// Only shown while a run is going and the player could teleport
+fn move_teleport_preview(
+    teleport: Res<Teleport>,
+    state: Res<State<GameState>>,
+    player_query: Query<&Transform, (With<Player>, Without<TeleportPreview>)>,
+    mut preview_query: Query<(&mut Transform, &mut Visible), With<TeleportPreview>>,
+) {
+    let target = match (state.current(), player_query.single()) {
+        (GameState::Playing, Ok(player)) => teleport
+            .target
+            .map(|target| (target, player.rotation, player.translation.z)),
+        _ => None,
+    };
+
+    for (mut transform, mut visible) in preview_query.iter_mut() {
+        visible.is_visible = target.is_some();
+
+        if let Some((target, rotation, z)) = target {
+            // Drawn under the player
+            transform.translation = target.extend(z - 0.5);
+            transform.rotation = rotation;
+        }
+    }
+}
+
+fn add_bullet_sprites(
+    mut commands: Commands,
+    sprite_materials: Res<SpriteMaterials>,
+    bullet_query: Query<(Entity, &Transform, &Dimensions), Added<Bullet>>,
+) {
+    for (entity, transform, dimensions) in bullet_query.iter() {
+        commands.entity(entity).insert_bundle(sprite_bundle(
+            sprite_materials.bullet.clone(),
+            transform,
+            dimensions,
+        ));
+    }
+}
+
+fn sprite_bundle(
+    material: Handle<ColorMaterial>,
+    transform: &Transform,
+    dimensions: &Dimensions,
+) -> SpriteBundle {
+    SpriteBundle {
+        sprite: Sprite::new(dimensions.0),
+        material,
+        transform: *transform,
+        // Set this up front so the sprite doesn't flash at the origin for a frame
+        global_transform: GlobalTransform::from(*transform),
+        ..Default::default()
+    }
+}
@@ -2,65 +2,243 @@
 
 use bevy::prelude::*;
 
-use crate::logic::{player::PlayerDeathEvent, reset_game::ResetGameEvent};
+use crate::assets::GameAssets;
+use crate::logic::difficulty::DifficultyPreset;
+use crate::logic::player::ControlScheme;
+use crate::logic::score::{Score, SurvivalTime};
+use crate::logic::state::{GameState, LoadingProgress};
 
 pub struct TextPlugin;
 
 impl Plugin for TextPlugin {
     fn build(&self, app: &mut AppBuilder) {
-        app.add_system(game_over_text.system())
-            .add_system(clear_game_over_text.system());
+        app.init_resource::<OverlayMaterial>()
+            .add_system_set(
+                SystemSet::on_enter(GameState::Loading).with_system(loading_text.system()),
+            )
+            .add_system_set(
+                SystemSet::on_update(GameState::Loading).with_system(refresh_loading_text.system()),
+            )
+            .add_system_set(
+                SystemSet::on_exit(GameState::Loading).with_system(clear_overlay_text.system()),
+            )
+            .add_system_set(SystemSet::on_enter(GameState::Title).with_system(title_text.system()))
+            .add_system_set(
+                SystemSet::on_exit(GameState::Title).with_system(clear_overlay_text.system()),
+            )
+            .add_system_set(
+                SystemSet::on_enter(GameState::Paused).with_system(paused_text.system()),
+            )
+            .add_system_set(
+                SystemSet::on_exit(GameState::Paused).with_system(clear_overlay_text.system()),
+            )
+            .add_system_set(
+                SystemSet::on_enter(GameState::GameOver).with_system(game_over_text.system()),
+            )
+            .add_system_set(
+                SystemSet::on_exit(GameState::GameOver).with_system(clear_overlay_text.system()),
+            )
+            .add_system_set(
+                SystemSet::on_update(GameState::Title).with_system(refresh_settings_text.system()),
+            )
+            .add_system_set(
+                SystemSet::on_update(GameState::GameOver)
+                    .with_system(refresh_settings_text.system()),
+            );
     }
 }
 
-struct GameOverText;
+// Text shown in the middle of the screen for the current game state
+struct OverlayText;
+
+// The see-through background behind the overlay text.
+// Made once, so changing state never adds to the material assets.
+struct OverlayMaterial(Handle<ColorMaterial>);
+
+impl FromWorld for OverlayMaterial {
+    fn from_world(world: &mut World) -> Self {
+        let mut materials = world
+            .get_resource_mut::<Assets<ColorMaterial>>()
+            .expect("The sprite plugin is added before the graphics plugins.");
+
+        OverlayMaterial(materials.add(Color::NONE.into()))
+    }
+}
+
+fn title_message(control_scheme: ControlScheme, preset: DifficultyPreset) -> String {
+    format!(
+        "Press Space or Start to start. Press P to pause.\n{}",
+        settings_message(control_scheme, preset)
+    )
+}
+
+// Shows where the run's points came from above the usual prompt
+fn game_over_message(
+    score: &Score,
+    survival_time: &SurvivalTime,
+    control_scheme: ControlScheme,
+    preset: DifficultyPreset,
+) -> String {
+    format!(
+        "{}\n\nPress R or Start to reset the game. Press ESC to quit.\n{}",
+        score.breakdown(survival_time),
+        settings_message(control_scheme, preset)
+    )
+}
+
+fn settings_message(control_scheme: ControlScheme, preset: DifficultyPreset) -> String {
+    format!(
+        "Press C to change the controls, now {}.\nPress Tab to change the difficulty, now {}.",
+        control_scheme.name(),
+        preset.name()
+    )
+}
+
+fn loading_message(loading_progress: &LoadingProgress) -> String {
+    format!(
+        "Loading {} of {}...",
+        loading_progress.loaded, loading_progress.total
+    )
+}
+
+// The assets are still loading, so this uses the font built into the game
+fn loading_text(
+    mut commands: Commands,
+    overlay_material: Res<OverlayMaterial>,
+    game_assets: Res<GameAssets>,
+    loading_progress: Res<LoadingProgress>,
+) {
+    spawn_overlay_text(
+        &mut commands,
+        &overlay_material,
+        game_assets.fallback_font(),
+        &loading_message(&loading_progress),
+    );
+}
+
+fn refresh_loading_text(
+    loading_progress: Res<LoadingProgress>,
+    mut overlay_text_query: Query<&mut Text, With<OverlayText>>,
+) {
+    if !loading_progress.is_changed() {
+        return;
+    }
+
+    for mut text in overlay_text_query.iter_mut() {
+        text.sections[0].value = loading_message(&loading_progress);
+    }
+}
+
+fn title_text(
+    mut commands: Commands,
+    overlay_material: Res<OverlayMaterial>,
+    game_assets: Res<GameAssets>,
+    control_scheme: Res<ControlScheme>,
+    preset: Res<DifficultyPreset>,
+) {
+    spawn_overlay_text(
+        &mut commands,
+        &overlay_material,
+        game_assets.font(),
+        &title_message(*control_scheme, *preset),
+    );
+}
+
+fn paused_text(
+    mut commands: Commands,
+    overlay_material: Res<OverlayMaterial>,
+    game_assets: Res<GameAssets>,
+) {
+    spawn_overlay_text(
+        &mut commands,
+        &overlay_material,
+        game_assets.font(),
+        "Paused. Press P or Start to continue.",
+    );
+}
 
 fn game_over_text(
     mut commands: Commands,
-    mut player_death_event: EventReader<PlayerDeathEvent>,
-    mut materials: ResMut<Assets<ColorMaterial>>,
-    asset_server: Res<AssetServer>,
+    overlay_material: Res<OverlayMaterial>,
+    game_assets: Res<GameAssets>,
+    score: Res<Score>,
+    survival_time: Res<SurvivalTime>,
+    control_scheme: Res<ControlScheme>,
+    preset: Res<DifficultyPreset>,
 ) {
-    for _event in player_death_event.iter() {
-        commands
-            .spawn_bundle(NodeBundle {
-                style: Style {
-                    align_items: AlignItems::Center,
-                    justify_content: JustifyContent::Center,
-                    size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
-                    ..Default::default()
-                },
-                material: materials.add(Color::NONE.into()),
-                ..Default::default()
-            })
-            .with_children(|parent| {
-                parent
-                    .spawn_bundle(TextBundle {
-                        text: Text::with_section(
-                            "Press R to reset the game. Press ESC to quit.",
-                            TextStyle {
-                                font: asset_server.load("fonts/Roboto-thin.ttf"),
-                                font_size: 40.0,
-                                color: Color::rgb(0.9, 0.9, 0.9),
-                            },
-                            Default::default(),
-                        ),
-                        ..Default::default()
-                    })
-                    .insert(GameOverText);
-            })
-            .insert(GameOverText);
+    spawn_overlay_text(
+        &mut commands,
+        &overlay_material,
+        game_assets.font(),
+        &game_over_message(&score, &survival_time, *control_scheme, *preset),
+    );
+}
+
+// Show the new control scheme or preset as soon as it is switched
+fn refresh_settings_text(
+    score: Res<Score>,
+    survival_time: Res<SurvivalTime>,
+    control_scheme: Res<ControlScheme>,
+    preset: Res<DifficultyPreset>,
+    state: Res<State<GameState>>,
+    mut overlay_text_query: Query<&mut Text, With<OverlayText>>,
+) {
+    if !control_scheme.is_changed() && !preset.is_changed() {
+        return;
+    }
+
+    let message = match state.current() {
+        GameState::Title => title_message(*control_scheme, *preset),
+        GameState::GameOver => game_over_message(&score, &survival_time, *control_scheme, *preset),
+        _ => return,
+    };
+
+    for mut text in overlay_text_query.iter_mut() {
+        text.sections[0].value = message.clone();
     }
 }
 
-fn clear_game_over_text(
+fn spawn_overlay_text(
+    commands: &mut Commands,
+    overlay_material: &OverlayMaterial,
+    font: Handle<Font>,
+    message: &str,
+) {
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                align_items: AlignItems::Center,
+                justify_content: JustifyContent::Center,
+                size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                ..Default::default()
+            },
+            material: overlay_material.0.clone(),
+            ..Default::default()
+        })
+        .with_children(|parent| {
+            parent
+                .spawn_bundle(TextBundle {
+                    text: Text::with_section(
+                        message,
+                        TextStyle {
+                            font,
+                            font_size: 40.0,
+                            color: Color::rgb(0.9, 0.9, 0.9),
+                        },
+                        Default::default(),
+                    ),
+                    ..Default::default()
+                })
+                .insert(OverlayText);
+        })
+        .insert(OverlayText);
+}
+
+fn clear_overlay_text(
     mut commands: Commands,
-    mut reset_game_event: EventReader<ResetGameEvent>,
-    game_over_text_query: Query<Entity, With<GameOverText>>,
+    overlay_text_query: Query<Entity, With<OverlayText>>,
 ) {
-    for _event in reset_game_event.iter() {
-        for entity in game_over_text_query.iter() {
-            commands.entity(entity).despawn();
-        }
+    for entity in overlay_text_query.iter() {
+        commands.entity(entity).despawn();
     }
 }
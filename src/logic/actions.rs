@@ -0,0 +1,445 @@
+// INPUT ACTIONS CODE
+
+use bevy::app::AppExit;
+use bevy::input::InputSystem;
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::storage;
+
+use super::gamepad::{Gamepads, TrackGamepads};
+
+// Bump this whenever KeyBindingsFile changes shape
+const KEY_BINDINGS_VERSION: u32 = 1;
+
+pub struct ActionsPlugin;
+
+impl Plugin for ActionsPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        // Windowed games load the bindings from disk before adding the plugins
+        app.init_resource::<KeyBindings>()
+            .init_resource::<InputActions>()
+            .add_system_to_stage(
+                CoreStage::PreUpdate,
+                update_input_actions
+                    .system()
+                    .after(InputSystem)
+                    .after(TrackGamepads),
+            )
+            .add_system(quit_game.system());
+    }
+}
+
+// Everything the player can ask the game to do.
+// Gameplay systems only ever look at these, never at keys or buttons.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum Action {
+    MoveUp,
+    MoveDown,
+    MoveLeft,
+    MoveRight,
+    Teleport,
+    Fire,
+    // Leaves the title screen
+    Start,
+    // Plays again after a game over
+    Reset,
+    Pause,
+    SwitchControls,
+    // Goes to the next difficulty preset
+    SwitchPreset,
+    // Shows or hides the debug readout
+    ToggleDebug,
+    Quit,
+}
+
+// Something on a device that can be bound to an action
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Binding {
+    Key(KeyCode),
+    Mouse(MouseButton),
+    // The button on any connected gamepad
+    Gamepad(GamepadButtonType),
+}
+
+// Which bindings trigger each action. Any one of them is enough.
+pub struct KeyBindings {
+    bindings: BTreeMap<Action, Vec<Binding>>,
+}
+
+// How the bindings are written to disk
+#[derive(Serialize, Deserialize)]
+struct KeyBindingsFile {
+    version: u32,
+    bindings: BTreeMap<Action, Vec<Binding>>,
+}
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        use Binding::{Gamepad, Key, Mouse};
+        use GamepadButtonType as Button;
+
+        let bindings = vec![
+            (
+                Action::MoveUp,
+                vec![Key(KeyCode::W), Key(KeyCode::Up), Gamepad(Button::DPadUp)],
+            ),
+            (
+                Action::MoveDown,
+                vec![
+                    Key(KeyCode::S),
+                    Key(KeyCode::Down),
+                    Gamepad(Button::DPadDown),
+                ],
+            ),
+            (
+                Action::MoveLeft,
+                vec![
+                    Key(KeyCode::A),
+                    Key(KeyCode::Left),
+                    Gamepad(Button::DPadLeft),
+                ],
+            ),
+            (
+                Action::MoveRight,
+                vec![
+                    Key(KeyCode::D),
+                    Key(KeyCode::Right),
+                    Gamepad(Button::DPadRight),
+                ],
+            ),
+            (
+                Action::Teleport,
+                vec![Key(KeyCode::Space), Gamepad(Button::South)],
+            ),
+            (
+                Action::Fire,
+                vec![
+                    Key(KeyCode::F),
+                    Key(KeyCode::LControl),
+                    Mouse(MouseButton::Left),
+                    Gamepad(Button::RightTrigger),
+                    Gamepad(Button::West),
+                ],
+            ),
+            (
+                Action::Start,
+                vec![
+                    Key(KeyCode::Space),
+                    Key(KeyCode::Return),
+                    Gamepad(Button::Start),
+                ],
+            ),
+            (Action::Reset, vec![Key(KeyCode::R), Gamepad(Button::Start)]),
+            (Action::Pause, vec![Key(KeyCode::P), Gamepad(Button::Start)]),
+            (
+                Action::SwitchControls,
+                vec![Key(KeyCode::C), Gamepad(Button::Select)],
+            ),
+            (
+                Action::SwitchPreset,
+                vec![Key(KeyCode::Tab), Gamepad(Button::North)],
+            ),
+            (Action::ToggleDebug, vec![Key(KeyCode::F3)]),
+            (Action::Quit, vec![Key(KeyCode::Escape)]),
+        ];
+
+        KeyBindings {
+            bindings: bindings.into_iter().collect(),
+        }
+    }
+}
+
+impl KeyBindings {
+    // A missing file is created with the default bindings so there is something to edit.
+    // A file that can't be read is left alone and the defaults are used instead.
+    // Actions the file doesn't mention keep their default bindings.
+    pub fn load(path: Option<PathBuf>) -> Self {
+        let path = match path {
+            Some(path) => path,
+            None => {
+                println!("Could not find a data directory, using the default key bindings.");
+                return KeyBindings::default();
+            }
+        };
+
+        let mut key_bindings = KeyBindings::default();
+
+        match read_bindings(&path) {
+            Ok(Some(bindings)) => {
+                let complete = key_bindings
+                    .bindings
+                    .keys()
+                    .all(|action| bindings.contains_key(action));
+
+                key_bindings.bindings.extend(bindings);
+
+                if complete {
+                    return key_bindings;
+                }
+            }
+            Ok(None) => {}
+            Err(problem) => {
+                println!(
+                    "Could not load the key bindings at {} ({}), using the defaults.",
+                    path.display(),
+                    problem
+                );
+                return key_bindings;
+            }
+        }
+
+        match key_bindings.save(&path) {
+            Ok(()) => println!("Saved the key bindings to {}.", path.display()),
+            Err(error) => println!("Could not save the key bindings: {}", error),
+        }
+
+        key_bindings
+    }
+
+    fn save(&self, path: &Path) -> io::Result<()> {
+        let file = KeyBindingsFile {
+            version: KEY_BINDINGS_VERSION,
+            bindings: self.bindings.clone(),
+        };
+
+        let contents = ron::ser::to_string_pretty(&file, Default::default())
+            .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))?;
+
+        storage::write_atomically(path, &contents)
+    }
+}
+
+pub fn key_bindings_path() -> Option<PathBuf> {
+    storage::data_dir().map(|directory| directory.join("key_bindings.ron"))
+}
+
+// None if there is no file yet
+fn read_bindings(path: &Path) -> Result<Option<BTreeMap<Action, Vec<Binding>>>, String> {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(error) => return Err(error.to_string()),
+    };
+
+    match ron::de::from_str::<KeyBindingsFile>(&contents) {
+        Ok(file) if file.version == KEY_BINDINGS_VERSION => Ok(Some(file.bindings)),
+        Ok(file) => Err(format!(
+            "it is version {}, expected {}",
+            file.version, KEY_BINDINGS_VERSION
+        )),
+        Err(error) => Err(error.to_string()),
+    }
+}
+
+// The actions being held and the ones that started this frame
+#[derive(Default)]
+pub struct InputActions {
+    pressed: HashSet<Action>,
+    just_pressed: HashSet<Action>,
+}
+
+impl InputActions {
+    pub fn pressed(&self, action: Action) -> bool {
+        self.pressed.contains(&action)
+    }
+
+    pub fn just_pressed(&self, action: Action) -> bool {
+        self.just_pressed.contains(&action)
+    }
+
+    // Whether the action just started, using up every fresh press this frame if it did.
+    //
+    // NOTE
+    // State changes happen within the same frame, so the next state would
+    // see the same press and act on it too. One key can be bound to more than
+    // one action, e.g. Space starts the game and teleports.
+    pub fn consume(&mut self, action: Action) -> bool {
+        let just_pressed = self.just_pressed(action);
+
+        if just_pressed {
+            self.just_pressed.clear();
+        }
+
+        just_pressed
+    }
+}
+
+fn update_input_actions(
+    keyboard_input: Res<Input<KeyCode>>,
+    mouse_input: Res<Input<MouseButton>>,
+    gamepad_buttons: Res<Input<GamepadButton>>,
+    gamepads: Res<Gamepads>,
+    key_bindings: Res<KeyBindings>,
+    mut input_actions: ResMut<InputActions>,
+) {
+    let held = |binding: &Binding| match *binding {
+        Binding::Key(key) => keyboard_input.pressed(key),
+        Binding::Mouse(button) => mouse_input.pressed(button),
+        Binding::Gamepad(button) => gamepads.pressed(&gamepad_buttons, button),
+    };
+
+    // Also true for a press that was let go of within the same frame
+    let started = |binding: &Binding| match *binding {
+        Binding::Key(key) => keyboard_input.just_pressed(key),
+        Binding::Mouse(button) => mouse_input.just_pressed(button),
+        Binding::Gamepad(button) => gamepads.just_pressed(&gamepad_buttons, button),
+    };
+
+    input_actions.pressed.clear();
+    input_actions.just_pressed.clear();
+
+    for (action, bindings) in key_bindings.bindings.iter() {
+        if bindings.iter().any(held) {
+            input_actions.pressed.insert(*action);
+        }
+
+        if bindings.iter().any(started) {
+            input_actions.just_pressed.insert(*action);
+        }
+    }
+}
+
+fn quit_game(input_actions: Res<InputActions>, mut exit: EventWriter<AppExit>) {
+    if input_actions.just_pressed(Action::Quit) {
+        exit.send(AppExit);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::app::Events;
+    use bevy::input::keyboard::KeyboardInput;
+    use bevy::input::ElementState;
+
+    use crate::config::GameConfig;
+    use crate::logic::gamepad::GamepadPlugin;
+    use crate::logic::state::GameState;
+    use crate::testing::{self, TemporaryDirectory};
+
+    fn headless_app(key_bindings: KeyBindings) -> App {
+        let mut builder = testing::headless_app(GameConfig::default());
+        builder
+            .insert_resource(key_bindings)
+            .add_state(GameState::Playing)
+            .add_plugin(GamepadPlugin)
+            .add_plugin(ActionsPlugin);
+        builder.app
+    }
+
+    // Runs one frame with the key going down or up
+    fn key(app: &mut App, key_code: KeyCode, state: ElementState) {
+        app.world
+            .get_resource_mut::<Events<KeyboardInput>>()
+            .unwrap()
+            .send(KeyboardInput {
+                scan_code: 0,
+                key_code: Some(key_code),
+                state,
+            });
+        app.update();
+    }
+
+    fn actions(app: &mut App) -> Mut<'_, InputActions> {
+        app.world.get_resource_mut::<InputActions>().unwrap()
+    }
+
+    #[test]
+    fn keys_trigger_every_action_they_are_bound_to() {
+        let mut app = headless_app(KeyBindings::default());
+
+        key(&mut app, KeyCode::Space, ElementState::Pressed);
+        assert!(actions(&mut app).just_pressed(Action::Start));
+        assert!(actions(&mut app).just_pressed(Action::Teleport));
+        assert!(actions(&mut app).pressed(Action::Teleport));
+
+        // Still held, but no longer new
+        app.update();
+        assert!(!actions(&mut app).just_pressed(Action::Teleport));
+        assert!(actions(&mut app).pressed(Action::Teleport));
+
+        key(&mut app, KeyCode::Space, ElementState::Released);
+        assert!(!actions(&mut app).pressed(Action::Teleport));
+    }
+
+    #[test]
+    fn consuming_an_action_uses_up_the_press() {
+        let mut app = headless_app(KeyBindings::default());
+
+        key(&mut app, KeyCode::Space, ElementState::Pressed);
+        assert!(actions(&mut app).consume(Action::Start));
+        assert!(!actions(&mut app).just_pressed(Action::Teleport));
+        assert!(!actions(&mut app).consume(Action::Start));
+    }
+
+    #[test]
+    fn rebound_actions_ignore_their_old_keys() {
+        let mut key_bindings = KeyBindings::default();
+        key_bindings
+            .bindings
+            .insert(Action::Teleport, vec![Binding::Key(KeyCode::T)]);
+        let mut app = headless_app(key_bindings);
+
+        key(&mut app, KeyCode::Space, ElementState::Pressed);
+        assert!(!actions(&mut app).pressed(Action::Teleport));
+
+        key(&mut app, KeyCode::T, ElementState::Pressed);
+        assert!(actions(&mut app).just_pressed(Action::Teleport));
+    }
+
+    #[test]
+    fn missing_bindings_file_is_created_with_the_defaults() {
+        let directory = TemporaryDirectory::new("bindings_created");
+        let path = directory.join("key_bindings.ron");
+
+        KeyBindings::load(Some(path.clone()));
+        let saved = read_bindings(&path).unwrap().unwrap();
+
+        assert_eq!(saved, KeyBindings::default().bindings);
+    }
+
+    #[test]
+    fn bindings_file_only_needs_the_changed_actions() {
+        let directory = TemporaryDirectory::new("bindings_partial");
+        let path = directory.join("key_bindings.ron");
+        storage::write_atomically(
+            &path,
+            "(version: 1, bindings: { Fire: [Key(J), Mouse(Right)] })",
+        )
+        .unwrap();
+
+        let key_bindings = KeyBindings::load(Some(path.clone()));
+        assert_eq!(
+            key_bindings.bindings[&Action::Fire],
+            vec![Binding::Key(KeyCode::J), Binding::Mouse(MouseButton::Right)]
+        );
+        assert_eq!(
+            key_bindings.bindings[&Action::Teleport],
+            KeyBindings::default().bindings[&Action::Teleport]
+        );
+
+        // The other actions are written back so they can be changed too
+        let saved = read_bindings(&path).unwrap().unwrap();
+        assert_eq!(saved, key_bindings.bindings);
+    }
+
+    #[test]
+    fn unreadable_bindings_file_is_left_alone() {
+        let directory = TemporaryDirectory::new("bindings_broken");
+        let path = directory.join("key_bindings.ron");
+        storage::write_atomically(&path, "(version: 1, bindings: { Jump: [] })").unwrap();
+
+        let key_bindings = KeyBindings::load(Some(path.clone()));
+
+        assert_eq!(key_bindings.bindings, KeyBindings::default().bindings);
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "(version: 1, bindings: { Jump: [] })"
+        );
+    }
+}
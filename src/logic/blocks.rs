@@ -1,34 +1,50 @@
 // BLOCKS CODE
 
 // use crate::logic::spawning::{SpawnInfo};
-use crate::{Collidable, Direction, BLOCKSIZEX, BLOCKSIZEY, WINDOWHEIGHT, WINDOWWIDTH};
+use crate::config::{BlockTierConfig, GameConfig};
+use crate::{Dimensions, Direction};
 
-use bevy::core::FixedTimestep;
+use bevy::math::Mat2;
 use bevy::prelude::*;
 use rand::distributions::{Distribution, Standard};
-use rand::seq::IteratorRandom;
-use rand::{thread_rng, Rng};
+use rand::seq::{IteratorRandom, SliceRandom};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
 
+use super::clock::GameClock;
+use super::collision::Collider;
+use super::difficulty::Difficulty;
+use super::rng::GameRng;
 use super::spawning::SpawnInfo;
-
-// For BLOCK_SPAWN_TIMESTEP, it's once every two seconds
-const BLOCK_SPAWN_TIMESTEP: f64 = 120.0 / 60.0;
+use super::state::GameState;
+use super::{wrap_around_screen, GameSystem};
 
 pub struct BlocksPlugin;
 
 impl Plugin for BlocksPlugin {
     fn build(&self, app: &mut AppBuilder) {
-        app
-            // Needs to be run after spawning logic
-            .add_event::<SpawnBlockEvent>()
-            .add_startup_system_to_stage(StartupStage::PostStartup, spawn_starting_block.system())
+        // The starting blocks are spawned when a run starts, see the reset game module
+        app.add_event::<BlockDestroyedEvent>()
+            .init_resource::<BlockSpawnTimer>()
+            .init_resource::<BlockPool>()
+            .init_resource::<BlockSpeed>()
             .add_system_set(
-                SystemSet::new()
-                    .with_run_criteria(FixedTimestep::step(BLOCK_SPAWN_TIMESTEP))
-                    .with_system(spawn_runtime_blocks.system()),
-            )
-            .add_system(move_blocks.system())
-            .add_system(spawn_block.system());
+                SystemSet::on_update(GameState::Playing)
+                    .with_system(spawn_runtime_blocks.system().after(GameSystem::Difficulty))
+                    .with_system(activate_warned_blocks.system())
+                    .with_system(
+                        move_blocks
+                            .system()
+                            .label(GameSystem::Movement)
+                            .after(GameSystem::Difficulty),
+                    )
+                    .with_system(
+                        break_destroyed_blocks
+                            .system()
+                            .after(GameSystem::CollisionResponse),
+                    ),
+            );
     }
 }
 
@@ -43,111 +59,421 @@ impl Distribution<Direction> for Standard {
     }
 }
 
+#[derive(Clone)]
 pub struct Block {
-    velocity: f32,
-    direction: Direction,
+    // in pixels per second, pointing the way the block moves
+    velocity: Vec2,
+    pub tier: BlockTier,
+    // The spawn position this block came from, freed when the block is destroyed.
+    // Pieces of a broken block didn't come from one.
+    slot: Option<Entity>,
+    // Counts up with every block that comes into play, the lowest is the oldest
+    order: u64,
 }
 
-pub struct SpawnBlockEvent(pub Entity);
+impl Block {
+    pub fn new(
+        direction: Vec2,
+        tier: BlockTier,
+        slot: Option<Entity>,
+        config: &GameConfig,
+    ) -> Self {
+        Block {
+            velocity: direction * tier.config(config).velocity,
+            tier,
+            slot,
+            // Set when it spawns
+            order: 0,
+        }
+    }
 
-// Spawns starting blocks for the game
-fn spawn_starting_block(
-    mut spawn_positions_query: Query<(Entity, With<SpawnInfo>)>,
-    mut spawn_event: EventWriter<SpawnBlockEvent>,
-) {
-    let block_number = 6;
-    for (entity, _boolean) in spawn_positions_query.iter_mut().take(block_number) {
-        spawn_event.send(SpawnBlockEvent(entity))
+    // in pixels per second, before the difficulty speeds it up
+    pub fn velocity(&self) -> Vec2 {
+        self.velocity
+    }
+
+    // Different every time a block comes into play, even when it is the same entity from the pool
+    pub fn order(&self) -> u64 {
+        self.order
+    }
+}
+
+// A block that is about to come in from its spawn position.
+// It sits still without a Block or Collider until the warning is over,
+// so it can't hit anything, and the graphics plugins make it flash.
+pub struct SpawnWarning {
+    pub timer: Timer,
+    // Added to the entity once the warning is over
+    pub block: Block,
+}
+
+// How big a block is. Shooting a block breaks it into two of the next size down.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BlockTier {
+    Large,
+    Medium,
+    Small,
+}
+
+impl BlockTier {
+    pub fn config(self, config: &GameConfig) -> &BlockTierConfig {
+        match self {
+            BlockTier::Large => &config.large_block,
+            BlockTier::Medium => &config.medium_block,
+            BlockTier::Small => &config.small_block,
+        }
+    }
+
+    // What this breaks into, small blocks are destroyed for good
+    fn smaller(self) -> Option<BlockTier> {
+        match self {
+            BlockTier::Large => Some(BlockTier::Medium),
+            BlockTier::Medium => Some(BlockTier::Small),
+            BlockTier::Small => None,
+        }
+    }
+}
+
+// Sent once for each block that gets destroyed, the block goes back to the
+// pool after everything that reacts to it has run
+pub struct BlockDestroyedEvent {
+    pub block: Entity,
+    pub tier: BlockTier,
+}
+
+// Blocks that aren't in play, kept around to be used again instead of despawned.
+// A pooled block has no Block or Collider, so nothing in the game sees it,
+// and the graphics plugins hide its sprite.
+//
+// NOTE
+// Which entity a block gets back from the pool can change between a run and
+// its replay, so nothing in the game decides anything by the entity.
+// Ties go to the oldest block instead, see Block::order.
+#[derive(Default)]
+pub struct BlockPool {
+    free: Vec<Entity>,
+    // How many blocks have come into play, gives each block its order
+    spawned: u64,
+}
+
+// Marks a block that is waiting in the pool
+pub struct PooledBlock;
+
+impl BlockPool {
+    // Takes the block out of play and keeps it for later
+    pub fn recycle(&mut self, commands: &mut Commands, block: Entity) {
+        // The commands from the first time haven't run yet
+        if self.free.contains(&block) {
+            return;
+        }
+
+        commands
+            .entity(block)
+            .remove::<Block>()
+            .remove::<Collider>()
+            .remove::<SpawnWarning>()
+            .insert(PooledBlock);
+
+        self.free.push(block);
+    }
+}
+
+// What to do when a block is due but every spawn position already has one in play
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SaturatedSpawnPolicy {
+    // Skip the spawn, the game stops getting harder
+    Wait,
+    // Take the oldest spawned block out of play and send it back in from
+    // its spawn position, in a new direction
+    Recycle,
+    // Every block on the board gets faster instead
+    SpeedUp,
+}
+
+impl Default for SaturatedSpawnPolicy {
+    fn default() -> Self {
+        SaturatedSpawnPolicy::SpeedUp
+    }
+}
+
+// How many times their normal speed the blocks move at, on top of the difficulty.
+// Goes up with the speed up policy and back to 1 when a run starts.
+pub struct BlockSpeed(pub f32);
+
+impl Default for BlockSpeed {
+    fn default() -> Self {
+        BlockSpeed(1.0)
+    }
+}
+
+// Ticked by the game clock so headless runs spawn at the same pace
+pub struct BlockSpawnTimer(pub Timer);
+
+impl FromWorld for BlockSpawnTimer {
+    fn from_world(world: &mut World) -> Self {
+        let config = world
+            .get_resource::<GameConfig>()
+            .expect("The game config is added before the plugins.");
+
+        BlockSpawnTimer(Timer::from_seconds(
+            config.starting_difficulty().spawn_interval,
+            true,
+        ))
     }
 }
 
 // spawns blocks as a way to make the game harder during runtime
-// this will only run every spawn block timestep
+// this will only spawn once every spawn interval from the difficulty
+#[allow(clippy::too_many_arguments)]
 fn spawn_runtime_blocks(
-    spawn_positions_query: Query<(Entity, &SpawnInfo)>,
-    mut spawn_event: EventWriter<SpawnBlockEvent>,
+    mut commands: Commands,
+    mut spawn_positions_query: Query<(Entity, &mut SpawnInfo)>,
+    block_query: Query<(Entity, &Block)>,
+    warning_query: Query<(), With<SpawnWarning>>,
+    mut spawn_timer: ResMut<BlockSpawnTimer>,
+    mut block_pool: ResMut<BlockPool>,
+    mut block_speed: ResMut<BlockSpeed>,
+    mut rng: ResMut<GameRng>,
+    difficulty: Res<Difficulty>,
+    clock: Res<GameClock>,
+    config: Res<GameConfig>,
 ) {
-    let mut rng = thread_rng();
+    let spawn_interval = Duration::from_secs_f32(difficulty.spawn_interval);
+    if spawn_timer.0.duration() != spawn_interval {
+        spawn_timer.0.set_duration(spawn_interval);
+    }
+
+    if !spawn_timer.0.tick(clock.delta_duration()).just_finished() {
+        return;
+    }
+
+    // The board is as full as the difficulty allows, counting the blocks about to come in
+    if block_query.iter().count() + warning_query.iter().count() >= difficulty.max_blocks {
+        return;
+    }
+
+    // Only roll for the size when there is a choice, so a single size
+    // doesn't change the random numbers the rest of the run sees
+    let tier = match difficulty.tiers.as_slice() {
+        [tier] => *tier,
+        tiers => *tiers
+            .choose(&mut *rng)
+            .expect("The config is validated to have at least one size."),
+    };
 
     let spawn_entity: Option<Entity> = spawn_positions_query
-        .iter()
+        .iter_mut()
         .filter(|(_entity, spawn_position)| !spawn_position.spawned)
         .map(|tuple| tuple.0)
-        .choose(&mut rng);
+        .choose(&mut *rng);
 
     match spawn_entity {
-        Some(entity) => spawn_event.send(SpawnBlockEvent(entity)),
-        // NOTE
-        // You could do the swap around stuff here at some point
-        None => println!("empty"),
+        Some(entity) => {
+            let (_entity, mut spawn_position) = spawn_positions_query
+                .get_mut(entity)
+                .expect("The entity was just picked from this query.");
+
+            spawn_block(
+                &mut commands,
+                &mut block_pool,
+                entity,
+                &mut spawn_position,
+                tier,
+                difficulty.spawn_warning,
+                &config,
+            );
+        }
+        // Every spawn position is taken
+        None => match config.saturated_spawn_policy {
+            SaturatedSpawnPolicy::Wait => {}
+            SaturatedSpawnPolicy::Recycle => {
+                // Only blocks from a spawn position can go back to one
+                let oldest = block_query
+                    .iter()
+                    .filter_map(|(entity, block)| Some((entity, block.slot?, block.order)))
+                    .min_by_key(|(_entity, _slot, order)| *order);
+
+                if let Some((entity, slot, _order)) = oldest {
+                    if let Ok((_slot, mut spawn_position)) = spawn_positions_query.get_mut(slot) {
+                        block_pool.recycle(&mut commands, entity);
+
+                        spawn_position.spawn_direction =
+                            spawn_position.direction.random_direction(&mut *rng);
+                        spawn_block(
+                            &mut commands,
+                            &mut block_pool,
+                            slot,
+                            &mut spawn_position,
+                            tier,
+                            difficulty.spawn_warning,
+                            &config,
+                        );
+                    }
+                }
+            }
+            SaturatedSpawnPolicy::SpeedUp => {
+                block_speed.0 =
+                    (block_speed.0 + config.saturated_speed_up).min(config.max_block_speed);
+            }
+        },
+    }
+}
+
+// Spawns a block of the given size at the given spawn position,
+// reusing one from the pool if there is one.
+// The block flashes there for the warning's seconds before it comes in.
+// The sprite is attached by the graphics plugins when there is a window
+//
+// NOTE
+// This spawns straight away instead of sending an event, so a block
+// always shows up on the tick after it was asked for. Replays rely on that.
+pub fn spawn_block(
+    commands: &mut Commands,
+    block_pool: &mut BlockPool,
+    slot: Entity,
+    spawn_position: &mut SpawnInfo,
+    tier: BlockTier,
+    warning: f32,
+    config: &GameConfig,
+) {
+    let location = spawn_position.spawn_location;
+    let direction = match spawn_position.spawn_direction {
+        Direction::Left => -Vec2::X,
+        Direction::Right => Vec2::X,
+        Direction::Up => Vec2::Y,
+        Direction::Down => -Vec2::Y,
+    };
+
+    // set the positions spawned value to true
+    spawn_position.spawned = true;
+
+    spawn_tiered_block(
+        commands,
+        block_pool,
+        Vec2::new(location.0 as f32, location.1 as f32),
+        Block::new(direction, tier, Some(slot), config),
+        warning,
+        config,
+    );
+}
+
+fn spawn_tiered_block(
+    commands: &mut Commands,
+    block_pool: &mut BlockPool,
+    position: Vec2,
+    mut block: Block,
+    warning: f32,
+    config: &GameConfig,
+) {
+    let tier_config = block.tier.config(config);
+    let size = Vec2::new(tier_config.size.0, tier_config.size.1);
+
+    block_pool.spawned += 1;
+    block.order = block_pool.spawned;
+
+    let mut entity = match block_pool.free.pop() {
+        Some(pooled) => {
+            let mut entity = commands.entity(pooled);
+            entity.remove::<PooledBlock>();
+            entity
+        }
+        None => commands.spawn(),
+    };
+
+    entity
+        .insert(Transform::from_translation(position.extend(1.0)))
+        .insert(Dimensions(size));
+
+    if warning > 0.0 {
+        entity.insert(SpawnWarning {
+            timer: Timer::from_seconds(warning, false),
+            block,
+        });
+    } else {
+        entity
+            .insert(block)
+            .insert(Collider::aabb(size).with_inset(config.block_hitbox_inset));
     }
 }
 
-// This is called by an event
-fn spawn_block(
+// Lets blocks in once their warning is over, they start moving on the next tick
+fn activate_warned_blocks(
     mut commands: Commands,
-    asset_server: Res<AssetServer>,
-    mut materials: ResMut<Assets<ColorMaterial>>,
-    mut spawn_event: EventReader<SpawnBlockEvent>,
-    mut spawn_query: Query<&mut SpawnInfo>,
+    mut warning_query: Query<(Entity, &mut SpawnWarning, &Dimensions)>,
+    clock: Res<GameClock>,
+    config: Res<GameConfig>,
 ) {
-    for event in spawn_event.iter() {
-        let entity: Entity = event.0;
-
-        if let Ok(mut spawn_position) = spawn_query.get_mut(entity) {
-            let texture_handle = asset_server.load("textures/block_1.png");
-
-            let location = spawn_position.spawn_location;
-            let direction = spawn_position.spawn_direction;
-
-            // set the positions spawned value to true
-            spawn_position.spawned = true;
-
-            commands
-                .spawn_bundle(SpriteBundle {
-                    sprite: Sprite::new(Vec2::new(BLOCKSIZEX, BLOCKSIZEY)),
-                    material: materials.add(texture_handle.into()),
-                    transform: Transform::from_xyz(location.0 as f32, location.1 as f32, 1.0),
-                    ..Default::default()
-                })
-                .insert(Block {
-                    velocity: 300.0,
-                    direction,
-                })
-                .insert(Collidable);
-        } else {
-            // the entity does not have the components from the query
-            println!("not here")
+    for (entity, mut warning, dimensions) in warning_query.iter_mut() {
+        if !warning.timer.tick(clock.delta_duration()).finished() {
+            continue;
         }
+
+        commands
+            .entity(entity)
+            .remove::<SpawnWarning>()
+            .insert(warning.block.clone())
+            .insert(Collider::aabb(dimensions.0).with_inset(config.block_hitbox_inset));
     }
 }
 
 // move the block by its own velocity
-fn move_blocks(mut block_query: Query<(&Block, &mut Transform, &Sprite)>, time: Res<Time>) {
-    for (block, mut transform, sprite) in block_query.iter_mut() {
-        let block_speed = block.velocity * time.delta_seconds();
-        match &block.direction {
-            Direction::Left => transform.translation.x -= block_speed,
-            Direction::Right => transform.translation.x += block_speed,
-            Direction::Up => transform.translation.y += block_speed,
-            Direction::Down => transform.translation.y -= block_speed,
-        };
+fn move_blocks(
+    mut block_query: Query<(&Block, &mut Transform, &Dimensions)>,
+    block_speed: Res<BlockSpeed>,
+    difficulty: Res<Difficulty>,
+    clock: Res<GameClock>,
+    config: Res<GameConfig>,
+) {
+    for (block, mut transform, dimensions) in block_query.iter_mut() {
+        let velocity = block.velocity * difficulty.block_speed * block_speed.0;
+        transform.translation += (velocity * clock.delta_seconds()).extend(0.0);
 
         // Wrap the block if they go off screen
-        if transform.translation.x > WINDOWWIDTH / 2.0 + sprite.size.x {
-            transform.translation.x = -WINDOWWIDTH / 2.0;
-        }
+        wrap_around_screen(&mut transform.translation, dimensions.0, &config);
+    }
+}
 
-        if transform.translation.x < -WINDOWWIDTH / 2.0 - sprite.size.x {
-            transform.translation.x = WINDOWWIDTH / 2.0;
-        }
+// Break every destroyed block into two smaller pieces that fly apart,
+// and free up its spawn position so it can spawn again
+fn break_destroyed_blocks(
+    mut commands: Commands,
+    mut block_destroyed_event: EventReader<BlockDestroyedEvent>,
+    block_query: Query<(&Block, &Transform)>,
+    mut spawn_positions_query: Query<&mut SpawnInfo>,
+    mut block_pool: ResMut<BlockPool>,
+    config: Res<GameConfig>,
+) {
+    for destroyed in block_destroyed_event.iter() {
+        let (block, transform) = match block_query.get(destroyed.block) {
+            Ok(block) => block,
+            Err(_) => continue,
+        };
 
-        if transform.translation.y > WINDOWHEIGHT / 2.0 + sprite.size.y {
-            transform.translation.y = -WINDOWHEIGHT / 2.0;
+        if let Some(slot) = block.slot {
+            if let Ok(mut spawn_position) = spawn_positions_query.get_mut(slot) {
+                spawn_position.spawned = false;
+            }
         }
 
-        if transform.translation.y < -WINDOWHEIGHT / 2.0 - sprite.size.y {
-            transform.translation.y = WINDOWHEIGHT / 2.0;
+        if let Some(smaller) = block.tier.smaller() {
+            let heading = block.velocity.normalize_or_zero();
+            let angle = config.fragment_angle.to_radians();
+
+            for side in [-1.0, 1.0].iter() {
+                let direction = Mat2::from_angle(angle * side) * heading;
+
+                // The pieces are already on screen, so they don't need a warning
+                spawn_tiered_block(
+                    &mut commands,
+                    &mut block_pool,
+                    transform.translation.truncate(),
+                    Block::new(direction, smaller, None, &config),
+                    0.0,
+                    &config,
+                );
+            }
         }
+
+        block_pool.recycle(&mut commands, destroyed.block);
     }
 }
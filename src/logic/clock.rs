@@ -0,0 +1,89 @@
+// CLOCK CODE
+
+use bevy::prelude::*;
+use std::time::Duration;
+
+// The game logic always moves forward in steps of this many seconds
+pub const TIMESTEP: f32 = 1.0 / 60.0;
+
+pub struct ClockPlugin;
+
+impl Plugin for ClockPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<GameClock>()
+            .add_system_to_stage(CoreStage::PreUpdate, advance_clock.system());
+    }
+}
+
+// The time source for all game logic.
+//
+// The simulation runs in fixed ticks of TIMESTEP seconds so the same
+// inputs always play out the same way, which replays rely on.
+// Frames where no tick is due get a delta of 0, so anything that
+// scales by the delta or ticks a timer with it simply stands still.
+//
+// In a window the clock ticks at most once per frame, whenever enough
+// real time has passed. Headless apps tick on every update, so they
+// can run the simulation as fast as the machine allows.
+pub struct GameClock {
+    realtime: bool,
+    accumulator: f32,
+    ticked: bool,
+}
+
+impl Default for GameClock {
+    fn default() -> Self {
+        GameClock::realtime()
+    }
+}
+
+impl GameClock {
+    pub fn realtime() -> Self {
+        GameClock {
+            realtime: true,
+            accumulator: 0.0,
+            ticked: false,
+        }
+    }
+
+    pub fn headless() -> Self {
+        GameClock {
+            realtime: false,
+            ..GameClock::realtime()
+        }
+    }
+
+    // Whether the simulation moves forward this frame
+    pub fn ticked(&self) -> bool {
+        self.ticked
+    }
+
+    pub fn delta_seconds(&self) -> f32 {
+        if self.ticked {
+            TIMESTEP
+        } else {
+            0.0
+        }
+    }
+
+    pub fn delta_duration(&self) -> Duration {
+        Duration::from_secs_f32(self.delta_seconds())
+    }
+}
+
+fn advance_clock(mut clock: ResMut<GameClock>, time: Res<Time>) {
+    if clock.realtime {
+        // NOTE
+        // Only one tick runs per frame, so never bank more than one
+        // tick of spare time. Below 60 fps the game slows down instead
+        // of trying to catch up.
+        clock.accumulator = (clock.accumulator + time.delta_seconds()).min(2.0 * TIMESTEP);
+        clock.ticked = clock.accumulator >= TIMESTEP;
+
+        if clock.ticked {
+            clock.accumulator -= TIMESTEP;
+        }
+    } else {
+        clock.ticked = true;
+    }
+}
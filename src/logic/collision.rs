@@ -0,0 +1,365 @@
+// COLLISION CODE
+
+use bevy::prelude::*;
+
+use super::blocks::Block;
+use super::clock::GameClock;
+use super::player::Player;
+use super::projectiles::Bullet;
+use super::spatial_hash::SpatialHash;
+use super::state::GameState;
+use super::GameSystem;
+
+pub struct CollisionPlugin;
+
+impl Plugin for CollisionPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_event::<CollisionEvent>().add_system_set(
+            SystemSet::on_update(GameState::Playing).with_system(
+                detect_collisions
+                    .system()
+                    .label(GameSystem::Collision)
+                    .after(GameSystem::Teleport),
+            ),
+        );
+    }
+}
+
+// The hitbox of an entity, centered on its transform.
+// Game logic only collides entities that have one of these.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Collider {
+    pub shape: Shape,
+    // Pixels shaved off every side of the shape, so near misses
+    // that only touch the corners of a sprite don't count
+    pub inset: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Shape {
+    // An axis aligned box, stored as half its width and height
+    Aabb { half_size: Vec2 },
+    Circle { radius: f32 },
+}
+
+impl Collider {
+    // A box the size of the given width and height
+    pub fn aabb(size: Vec2) -> Self {
+        Collider {
+            shape: Shape::Aabb {
+                half_size: size / 2.0,
+            },
+            inset: 0.0,
+        }
+    }
+
+    pub fn circle(radius: f32) -> Self {
+        Collider {
+            shape: Shape::Circle { radius },
+            inset: 0.0,
+        }
+    }
+
+    pub fn with_inset(mut self, inset: f32) -> Self {
+        self.inset = inset;
+        self
+    }
+
+    // The shape with the inset taken off, never smaller than a point
+    fn hitbox(&self) -> Shape {
+        match self.shape {
+            Shape::Aabb { half_size } => Shape::Aabb {
+                half_size: (half_size - Vec2::splat(self.inset)).max(Vec2::ZERO),
+            },
+            Shape::Circle { radius } => Shape::Circle {
+                radius: (radius - self.inset).max(0.0),
+            },
+        }
+    }
+
+    // Half the width and height of the smallest box around the hitbox
+    pub fn half_extents(&self) -> Vec2 {
+        match self.hitbox() {
+            Shape::Aabb { half_size } => half_size,
+            Shape::Circle { radius } => Vec2::splat(radius),
+        }
+    }
+
+    // Whether two colliders at the given positions overlap.
+    // Shapes that only touch along an edge don't count, the same as bevy's collide.
+    pub fn overlaps(&self, position: Vec2, other: &Collider, other_position: Vec2) -> bool {
+        let offset = other_position - position;
+
+        match (self.hitbox(), other.hitbox()) {
+            (
+                Shape::Aabb { half_size },
+                Shape::Aabb {
+                    half_size: other_half,
+                },
+            ) => {
+                let reach = half_size + other_half;
+                offset.x.abs() < reach.x && offset.y.abs() < reach.y
+            }
+            (
+                Shape::Circle { radius },
+                Shape::Circle {
+                    radius: other_radius,
+                },
+            ) => {
+                let reach = radius + other_radius;
+                offset.length_squared() < reach * reach
+            }
+            (Shape::Aabb { half_size }, Shape::Circle { radius }) => {
+                aabb_overlaps_circle(half_size, offset, radius)
+            }
+            (Shape::Circle { radius }, Shape::Aabb { half_size }) => {
+                aabb_overlaps_circle(half_size, -offset, radius)
+            }
+        }
+    }
+}
+
+// The box is at the origin and the circle is offset from it
+fn aabb_overlaps_circle(half_size: Vec2, circle_offset: Vec2, radius: f32) -> bool {
+    let closest_point = circle_offset.max(-half_size).min(half_size);
+    (circle_offset - closest_point).length_squared() < radius * radius
+}
+
+// What collided with what
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum CollisionKind {
+    // a is the player, b is the block
+    PlayerBlock,
+    // a is the bullet, b is the block
+    BulletBlock,
+}
+
+// Where a collider comes in the simulation, ties between colliders are broken by this.
+//
+// NOTE
+// Entity ids depend on everything spawned before, windows and menus included,
+// so a run and its replay can give the same block different ids.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ColliderOrder {
+    Player,
+    Bullet(u64),
+    Block(u64),
+}
+
+// Sent once for every pair of entities that overlap on a tick.
+// The whole tick's events are sent together, sorted by kind and then oldest first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CollisionEvent {
+    pub a: Entity,
+    pub b: Entity,
+    pub kind: CollisionKind,
+}
+
+// NOTE
+// This is the only system that finds overlaps, everything that reacts to them
+// reads the events after GameSystem::Collision instead of checking itself.
+fn detect_collisions(
+    player_query: Query<(Entity, &Collider, &Transform), With<Player>>,
+    bullet_query: Query<(Entity, &Collider, &Transform, &Bullet)>,
+    block_query: Query<(&Collider, &Transform, &Block)>,
+    mut collision_event: EventWriter<CollisionEvent>,
+    spatial_hash: Res<SpatialHash>,
+    clock: Res<GameClock>,
+) {
+    // Nothing has moved since the last tick, but a block spawned in between
+    // could overlap the player. Wait for the tick so replays see the same thing.
+    if !clock.ticked() {
+        return;
+    }
+
+    let movers = player_query
+        .iter()
+        .map(|mover| (mover, ColliderOrder::Player, CollisionKind::PlayerBlock))
+        .chain(
+            bullet_query
+                .iter()
+                .map(|(entity, collider, transform, bullet)| {
+                    (
+                        (entity, collider, transform),
+                        ColliderOrder::Bullet(bullet.order()),
+                        CollisionKind::BulletBlock,
+                    )
+                }),
+        );
+
+    let mut collisions = Vec::new();
+
+    for ((entity, collider, transform), order, kind) in movers {
+        let position = transform.translation.truncate();
+
+        for other in spatial_hash.nearby(entity, 0.0) {
+            // Nearby entities that aren't blocks are skipped here
+            let (block_collider, block_transform, block) = match block_query.get(other) {
+                Ok(block) => block,
+                Err(_) => continue,
+            };
+            let block_position = block_transform.translation.truncate();
+
+            if collider.overlaps(position, block_collider, block_position) {
+                collisions.push((
+                    order,
+                    ColliderOrder::Block(block.order()),
+                    CollisionEvent {
+                        a: entity,
+                        b: other,
+                        kind,
+                    },
+                ));
+            }
+        }
+    }
+
+    collision_event.send_batch(deduplicate(collisions).into_iter());
+}
+
+// Sorts the events by kind and the order of what collided,
+// and drops any pair that shows up more than once
+fn deduplicate(
+    mut collisions: Vec<(ColliderOrder, ColliderOrder, CollisionEvent)>,
+) -> Vec<CollisionEvent> {
+    collisions.sort_by_key(|(a, b, collision)| (collision.kind, *a, *b));
+    collisions.dedup_by_key(|(_a, _b, collision)| *collision);
+    collisions
+        .into_iter()
+        .map(|(_a, _b, collision)| collision)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn square(size: f32) -> Collider {
+        Collider::aabb(Vec2::splat(size))
+    }
+
+    #[test]
+    fn aabbs_overlap_when_their_extents_cross() {
+        assert!(square(40.0).overlaps(Vec2::ZERO, &square(40.0), Vec2::new(39.0, 0.0)));
+        assert!(square(40.0).overlaps(Vec2::ZERO, &square(40.0), Vec2::new(-39.0, 39.0)));
+        assert!(!square(40.0).overlaps(Vec2::ZERO, &square(40.0), Vec2::new(41.0, 0.0)));
+        assert!(!square(40.0).overlaps(Vec2::ZERO, &square(40.0), Vec2::new(0.0, -41.0)));
+    }
+
+    #[test]
+    fn aabbs_that_only_touch_do_not_overlap() {
+        assert!(!square(40.0).overlaps(Vec2::ZERO, &square(40.0), Vec2::new(40.0, 0.0)));
+        assert!(!square(40.0).overlaps(Vec2::ZERO, &square(40.0), Vec2::new(40.0, 40.0)));
+    }
+
+    #[test]
+    fn aabbs_of_different_sizes_use_both_sizes() {
+        let small = square(10.0);
+        let large = square(100.0);
+
+        // 5 + 50 = 55 pixels of reach, which the old check got wrong
+        assert!(small.overlaps(Vec2::ZERO, &large, Vec2::new(54.0, 0.0)));
+        assert!(large.overlaps(Vec2::new(54.0, 0.0), &small, Vec2::ZERO));
+        assert!(!small.overlaps(Vec2::ZERO, &large, Vec2::new(56.0, 0.0)));
+    }
+
+    #[test]
+    fn rectangular_aabbs_use_each_axis() {
+        let wide = Collider::aabb(Vec2::new(100.0, 10.0));
+
+        assert!(wide.overlaps(Vec2::ZERO, &square(10.0), Vec2::new(54.0, 0.0)));
+        assert!(!wide.overlaps(Vec2::ZERO, &square(10.0), Vec2::new(0.0, 11.0)));
+    }
+
+    #[test]
+    fn circles_overlap_within_their_combined_radius() {
+        let circle = Collider::circle(10.0);
+
+        assert!(circle.overlaps(Vec2::ZERO, &circle, Vec2::new(19.0, 0.0)));
+        assert!(!circle.overlaps(Vec2::ZERO, &circle, Vec2::new(20.0, 0.0)));
+        // 14.9 along both axes is 21 pixels away
+        assert!(!circle.overlaps(Vec2::ZERO, &circle, Vec2::new(14.9, 14.9)));
+        assert!(circle.overlaps(Vec2::ZERO, &Collider::circle(30.0), Vec2::new(0.0, 39.0)));
+    }
+
+    #[test]
+    fn aabb_and_circle_overlap_along_an_edge() {
+        let circle = Collider::circle(10.0);
+
+        assert!(square(40.0).overlaps(Vec2::ZERO, &circle, Vec2::new(29.0, 0.0)));
+        assert!(!square(40.0).overlaps(Vec2::ZERO, &circle, Vec2::new(31.0, 0.0)));
+        assert!(circle.overlaps(Vec2::new(0.0, -29.0), &square(40.0), Vec2::ZERO));
+        assert!(!circle.overlaps(Vec2::new(0.0, -31.0), &square(40.0), Vec2::ZERO));
+    }
+
+    #[test]
+    fn aabb_and_circle_do_not_overlap_past_a_corner() {
+        let circle = Collider::circle(10.0);
+
+        // Inside the box's reach on both axes, but 11.3 pixels from the corner
+        let near_corner = Vec2::new(28.0, 28.0);
+        assert!(!square(40.0).overlaps(Vec2::ZERO, &circle, near_corner));
+        assert!(!circle.overlaps(near_corner, &square(40.0), Vec2::ZERO));
+
+        assert!(square(40.0).overlaps(Vec2::ZERO, &circle, Vec2::new(26.0, 26.0)));
+    }
+
+    #[test]
+    fn circle_inside_an_aabb_overlaps() {
+        assert!(square(40.0).overlaps(Vec2::ZERO, &Collider::circle(1.0), Vec2::new(5.0, -5.0)));
+    }
+
+    #[test]
+    fn inset_shrinks_the_hitbox() {
+        let inset_square = square(40.0).with_inset(5.0);
+
+        assert!(square(40.0).overlaps(Vec2::ZERO, &square(40.0), Vec2::new(36.0, 0.0)));
+        assert!(!inset_square.overlaps(Vec2::ZERO, &square(40.0), Vec2::new(36.0, 0.0)));
+        assert!(inset_square.overlaps(Vec2::ZERO, &square(40.0), Vec2::new(34.0, 0.0)));
+
+        let inset_circle = Collider::circle(10.0).with_inset(5.0);
+        assert!(!inset_circle.overlaps(Vec2::ZERO, &Collider::circle(10.0), Vec2::new(16.0, 0.0)));
+        assert!(inset_circle.overlaps(Vec2::ZERO, &Collider::circle(10.0), Vec2::new(14.0, 0.0)));
+    }
+
+    #[test]
+    fn inset_larger_than_the_shape_leaves_a_point() {
+        let point = square(10.0).with_inset(20.0);
+
+        assert!(point.overlaps(Vec2::ZERO, &square(40.0), Vec2::new(19.0, 0.0)));
+        assert!(!point.overlaps(
+            Vec2::ZERO,
+            &Collider::circle(10.0).with_inset(20.0),
+            Vec2::ZERO
+        ));
+    }
+
+    #[test]
+    fn each_pair_collides_once_in_a_fixed_order() {
+        let player = (Entity::new(1), ColliderOrder::Player);
+        let bullet = (Entity::new(2), ColliderOrder::Bullet(1));
+        // The older block has the higher entity, the order is what counts
+        let block = (Entity::new(4), ColliderOrder::Block(1));
+        let other_block = (Entity::new(3), ColliderOrder::Block(2));
+
+        let event = |(a, _), (b, _), kind| CollisionEvent { a, b, kind };
+        let ordered = |a: (Entity, ColliderOrder), b: (Entity, ColliderOrder), kind| {
+            (a.1, b.1, event(a, b, kind))
+        };
+        let collisions = deduplicate(vec![
+            ordered(bullet, block, CollisionKind::BulletBlock),
+            ordered(player, other_block, CollisionKind::PlayerBlock),
+            ordered(player, block, CollisionKind::PlayerBlock),
+            ordered(player, other_block, CollisionKind::PlayerBlock),
+        ]);
+
+        assert_eq!(
+            collisions,
+            vec![
+                event(player, block, CollisionKind::PlayerBlock),
+                event(player, other_block, CollisionKind::PlayerBlock),
+                event(bullet, block, CollisionKind::BulletBlock),
+            ]
+        );
+    }
+}
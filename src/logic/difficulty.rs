@@ -0,0 +1,303 @@
+// DIFFICULTY CODE
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::config::{DifficultyCurve, DifficultyProgress, GameConfig};
+
+use super::blocks::BlockTier;
+use super::clock::GameClock;
+use super::score::Score;
+use super::state::GameState;
+use super::{GameSystem, RunSettings};
+
+pub struct DifficultyPlugin;
+
+impl Plugin for DifficultyPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<Difficulty>()
+            .init_resource::<DifficultyPreset>()
+            .add_system_set(
+                SystemSet::on_update(GameState::Playing).with_system(
+                    update_difficulty
+                        .system()
+                        .label(GameSystem::Difficulty)
+                        .before(GameSystem::Movement),
+                ),
+            )
+            .add_system_set(
+                SystemSet::on_enter(GameState::Playing)
+                    .with_system(reset_difficulty.system().after(RunSettings)),
+            );
+    }
+}
+
+// A named bundle of tweaks on top of the config, picked before each run.
+// High scores and replays keep the preset so runs on different ones aren't compared.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DifficultyPreset {
+    Easy,
+    // Plays exactly as the config says
+    Normal,
+    Hard,
+    Insane,
+}
+
+impl Default for DifficultyPreset {
+    fn default() -> Self {
+        DifficultyPreset::Normal
+    }
+}
+
+// How a preset changes the config, each value is times the one in the config
+pub struct PresetSettings {
+    pub starting_blocks: f32,
+    pub block_speed: f32,
+    // Only the arcade controls, the classic ship keeps its own tuning
+    pub player_speed: f32,
+    pub teleport_distance: f32,
+    // Lower spawns blocks more often
+    pub spawn_interval: f32,
+}
+
+impl DifficultyPreset {
+    pub fn name(self) -> &'static str {
+        match self {
+            DifficultyPreset::Easy => "easy",
+            DifficultyPreset::Normal => "normal",
+            DifficultyPreset::Hard => "hard",
+            DifficultyPreset::Insane => "insane",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "easy" => Some(DifficultyPreset::Easy),
+            "normal" => Some(DifficultyPreset::Normal),
+            "hard" => Some(DifficultyPreset::Hard),
+            "insane" => Some(DifficultyPreset::Insane),
+            _ => None,
+        }
+    }
+
+    // The next one up, going back to easy after insane
+    pub fn next(self) -> Self {
+        match self {
+            DifficultyPreset::Easy => DifficultyPreset::Normal,
+            DifficultyPreset::Normal => DifficultyPreset::Hard,
+            DifficultyPreset::Hard => DifficultyPreset::Insane,
+            DifficultyPreset::Insane => DifficultyPreset::Easy,
+        }
+    }
+
+    pub fn settings(self) -> PresetSettings {
+        let (starting_blocks, block_speed, player_speed, teleport_distance, spawn_interval) =
+            match self {
+                DifficultyPreset::Easy => (0.5, 0.8, 1.1, 1.25, 1.4),
+                DifficultyPreset::Normal => (1.0, 1.0, 1.0, 1.0, 1.0),
+                DifficultyPreset::Hard => (1.5, 1.2, 1.0, 0.9, 0.75),
+                DifficultyPreset::Insane => (2.0, 1.45, 1.0, 0.8, 0.55),
+            };
+
+        PresetSettings {
+            starting_blocks,
+            block_speed,
+            player_speed,
+            teleport_distance,
+            spawn_interval,
+        }
+    }
+
+    pub fn starting_blocks(self, config: &GameConfig) -> usize {
+        (config.starting_blocks as f32 * self.settings().starting_blocks).round() as usize
+    }
+}
+
+// How hard the game is right now, read off the difficulty curve in the config
+// and adjusted by the preset
+#[derive(Debug, Clone, PartialEq)]
+pub struct Difficulty {
+    // Seconds since the run started
+    pub elapsed: f32,
+    // How far along the curve the run is, in seconds or points
+    pub progress: f32,
+    // The last keyframe reached, counting from 0
+    pub level: usize,
+    pub spawn_interval: f32,
+    pub spawn_warning: f32,
+    pub block_speed: f32,
+    pub max_blocks: usize,
+    pub tiers: Vec<BlockTier>,
+}
+
+impl FromWorld for Difficulty {
+    fn from_world(world: &mut World) -> Self {
+        let config = world
+            .get_resource::<GameConfig>()
+            .expect("The game config is added before the plugins.");
+
+        let preset = world
+            .get_resource::<DifficultyPreset>()
+            .copied()
+            .unwrap_or_default();
+
+        Difficulty::sample(&config.difficulty, 0.0, 0.0).with_preset(preset)
+    }
+}
+
+impl Difficulty {
+    // The difficulty at some point along the curve.
+    // Before the first keyframe it is the first one, after the last it stays at the last.
+    pub fn sample(curve: &DifficultyCurve, elapsed: f32, progress: f32) -> Self {
+        let keyframes = &curve.keyframes;
+
+        let level = keyframes
+            .iter()
+            .rposition(|keyframe| keyframe.at <= progress)
+            .unwrap_or(0);
+        let current = &keyframes[level];
+
+        // How far it is to the next keyframe, from 0 to 1
+        let (next, blend) = match keyframes.get(level + 1) {
+            Some(next) if progress > current.at => {
+                (next, (progress - current.at) / (next.at - current.at))
+            }
+            _ => (current, 0.0),
+        };
+
+        let lerp = |from: f32, to: f32| from + (to - from) * blend;
+
+        Difficulty {
+            elapsed,
+            progress,
+            level,
+            spawn_interval: lerp(current.spawn_interval, next.spawn_interval),
+            spawn_warning: lerp(current.spawn_warning, next.spawn_warning),
+            block_speed: lerp(current.block_speed, next.block_speed),
+            max_blocks: current.max_blocks,
+            tiers: current.tiers.clone(),
+        }
+    }
+
+    pub fn with_preset(mut self, preset: DifficultyPreset) -> Self {
+        let settings = preset.settings();
+        self.spawn_interval *= settings.spawn_interval;
+        self.block_speed *= settings.block_speed;
+        self
+    }
+
+    // One line about everything the difficulty controls, for debugging
+    pub fn summary(&self) -> String {
+        let tiers: Vec<String> = self
+            .tiers
+            .iter()
+            .map(|tier| format!("{:?}", tier).to_lowercase())
+            .collect();
+
+        format!(
+            "a block every {:.2} seconds with {:.2} seconds of warning at {:.2} times the speed, at most {} blocks, sizes {}.",
+            self.spawn_interval,
+            self.spawn_warning,
+            self.block_speed,
+            self.max_blocks,
+            tiers.join(", ")
+        )
+    }
+}
+
+// NOTE
+// The score is read at the start of the tick, so the points from
+// this tick's destroyed blocks count from the next one.
+fn update_difficulty(
+    mut difficulty: ResMut<Difficulty>,
+    preset: Res<DifficultyPreset>,
+    score: Res<Score>,
+    clock: Res<GameClock>,
+    config: Res<GameConfig>,
+) {
+    if !clock.ticked() {
+        return;
+    }
+
+    let elapsed = difficulty.elapsed + clock.delta_seconds();
+    let progress = match config.difficulty.progress {
+        DifficultyProgress::Time => elapsed,
+        DifficultyProgress::Score => score.value.max(0) as f32,
+    };
+
+    let next = Difficulty::sample(&config.difficulty, elapsed, progress).with_preset(*preset);
+
+    if next.level != difficulty.level {
+        println!(
+            "Difficulty level {} after {:.0} seconds: {}",
+            next.level,
+            elapsed,
+            next.summary()
+        );
+    }
+
+    *difficulty = next;
+}
+
+fn reset_difficulty(
+    mut difficulty: ResMut<Difficulty>,
+    preset: Res<DifficultyPreset>,
+    config: Res<GameConfig>,
+) {
+    *difficulty = Difficulty::sample(&config.difficulty, 0.0, 0.0).with_preset(*preset);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::DifficultyKeyframe;
+
+    fn curve() -> DifficultyCurve {
+        DifficultyCurve {
+            progress: DifficultyProgress::Time,
+            keyframes: vec![
+                DifficultyKeyframe::new(10.0, 2.0, 1.0, 1.0, 10, &[BlockTier::Large]),
+                DifficultyKeyframe::new(
+                    20.0,
+                    1.0,
+                    0.5,
+                    2.0,
+                    20,
+                    &[BlockTier::Large, BlockTier::Medium],
+                ),
+            ],
+        }
+    }
+
+    #[test]
+    fn holds_the_first_keyframe_until_it_is_reached() {
+        let difficulty = Difficulty::sample(&curve(), 5.0, 5.0);
+
+        assert_eq!(difficulty.level, 0);
+        assert_eq!(difficulty.spawn_interval, 2.0);
+        assert_eq!(difficulty.block_speed, 1.0);
+    }
+
+    #[test]
+    fn blends_between_keyframes() {
+        let difficulty = Difficulty::sample(&curve(), 15.0, 15.0);
+
+        assert_eq!(difficulty.level, 0);
+        assert!((difficulty.spawn_interval - 1.5).abs() < 1e-6);
+        assert!((difficulty.block_speed - 1.5).abs() < 1e-6);
+        // Only the smooth values blend, the rest wait for the keyframe
+        assert_eq!(difficulty.max_blocks, 10);
+        assert_eq!(difficulty.tiers, vec![BlockTier::Large]);
+    }
+
+    #[test]
+    fn stays_at_the_last_keyframe() {
+        let difficulty = Difficulty::sample(&curve(), 100.0, 100.0);
+
+        assert_eq!(difficulty.level, 1);
+        assert_eq!(difficulty.spawn_interval, 1.0);
+        assert_eq!(difficulty.block_speed, 2.0);
+        assert_eq!(difficulty.max_blocks, 20);
+        assert_eq!(difficulty.tiers, vec![BlockTier::Large, BlockTier::Medium]);
+    }
+}
@@ -0,0 +1,419 @@
+// GAMEPAD CODE
+
+use bevy::ecs::schedule::SystemLabel;
+use bevy::input::InputSystem;
+use bevy::prelude::*;
+
+use crate::config::GameConfig;
+
+use super::replay::Playback;
+use super::state::GameState;
+
+pub struct GamepadPlugin;
+
+impl Plugin for GamepadPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<Gamepads>()
+            // So every system in the frame agrees on which pads are plugged in
+            .add_system_to_stage(
+                CoreStage::PreUpdate,
+                track_gamepads
+                    .system()
+                    .label(TrackGamepads)
+                    .after(InputSystem),
+            )
+            .add_system_set(
+                SystemSet::on_update(GameState::Playing).with_system(pause_on_disconnect.system()),
+            );
+    }
+}
+
+// Runs right after bevy has turned the raw events into gamepad events
+#[derive(Debug, Clone, PartialEq, Eq, Hash, SystemLabel)]
+pub struct TrackGamepads;
+
+// Every gamepad that is plugged in, in the order they were connected.
+// Any of them can play, which matters when one is unplugged mid run.
+pub struct Gamepads {
+    connected: Vec<Gamepad>,
+    // The pad the player last used, None while they are on the keyboard and mouse
+    driving: Option<Gamepad>,
+    // Set on the frame the driving pad is unplugged
+    driving_disconnected: bool,
+    // How far the sticks have to be pushed before they count, from 0 to 1
+    dead_zone: f32,
+}
+
+impl FromWorld for Gamepads {
+    fn from_world(world: &mut World) -> Self {
+        let config = world
+            .get_resource::<GameConfig>()
+            .expect("The game config is added before the plugins.");
+
+        Gamepads {
+            connected: Vec::new(),
+            driving: None,
+            driving_disconnected: false,
+            dead_zone: config.gamepad_dead_zone,
+        }
+    }
+}
+
+impl Gamepads {
+    pub fn iter(&self) -> impl Iterator<Item = Gamepad> + '_ {
+        self.connected.iter().copied()
+    }
+
+    pub fn pressed(&self, buttons: &Input<GamepadButton>, button_type: GamepadButtonType) -> bool {
+        self.iter()
+            .any(|gamepad| buttons.pressed(GamepadButton(gamepad, button_type)))
+    }
+
+    pub fn just_pressed(
+        &self,
+        buttons: &Input<GamepadButton>,
+        button_type: GamepadButtonType,
+    ) -> bool {
+        self.iter()
+            .any(|gamepad| buttons.just_pressed(GamepadButton(gamepad, button_type)))
+    }
+
+    // The left stick of the first pad that is pushed past the dead zone
+    pub fn left_stick(&self, axes: &Axis<GamepadAxis>) -> Vec2 {
+        self.iter()
+            .map(|gamepad| self.stick_of(axes, gamepad))
+            .find(|stick| *stick != Vec2::ZERO)
+            .unwrap_or(Vec2::ZERO)
+    }
+
+    fn stick_of(&self, axes: &Axis<GamepadAxis>, gamepad: Gamepad) -> Vec2 {
+        let x = axes.get(GamepadAxis(gamepad, GamepadAxisType::LeftStickX));
+        let y = axes.get(GamepadAxis(gamepad, GamepadAxisType::LeftStickY));
+        apply_dead_zone(
+            Vec2::new(x.unwrap_or(0.0), y.unwrap_or(0.0)),
+            self.dead_zone,
+        )
+    }
+}
+
+// Sticks rarely rest at exactly the center, so anything within the dead zone counts as
+// centered. The rest of the range is stretched back out to go from 0 to 1, so the
+// speed grows smoothly from the edge of the dead zone instead of jumping.
+//
+// The dead zone is round, so the stick can point any way and not snap to an axis.
+pub fn apply_dead_zone(stick: Vec2, dead_zone: f32) -> Vec2 {
+    let length = stick.length();
+
+    if length <= dead_zone {
+        return Vec2::ZERO;
+    }
+
+    // Square pads can report a little over 1 in the corners
+    let strength = ((length - dead_zone) / (1.0 - dead_zone)).min(1.0);
+    stick / length * strength
+}
+
+// Keeps the list of pads up to date, and which of them the player is using
+// so unplugging a spare pad doesn't pause the game.
+fn track_gamepads(
+    mut gamepad_event: EventReader<GamepadEvent>,
+    gamepad_buttons: Res<Input<GamepadButton>>,
+    gamepad_axes: Res<Axis<GamepadAxis>>,
+    keys: Res<Input<KeyCode>>,
+    mouse_buttons: Res<Input<MouseButton>>,
+    mut gamepads: ResMut<Gamepads>,
+) {
+    gamepads.driving_disconnected = false;
+
+    for GamepadEvent(gamepad, event_type) in gamepad_event.iter() {
+        match event_type {
+            GamepadEventType::Connected if !gamepads.connected.contains(gamepad) => {
+                gamepads.connected.push(*gamepad);
+                println!("Gamepad {} connected.", gamepad.0);
+            }
+            GamepadEventType::Disconnected => {
+                if let Some(index) = gamepads.connected.iter().position(|pad| pad == gamepad) {
+                    gamepads.connected.remove(index);
+                    println!("Gamepad {} disconnected.", gamepad.0);
+                }
+                if gamepads.driving == Some(*gamepad) {
+                    gamepads.driving = None;
+                    gamepads.driving_disconnected = true;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    if keys.get_just_pressed().next().is_some() || mouse_buttons.get_just_pressed().next().is_some()
+    {
+        gamepads.driving = None;
+    }
+
+    // A pad that is being used right now takes over from the keyboard
+    let used = gamepads.iter().find(|gamepad| {
+        gamepad_buttons
+            .get_pressed()
+            .any(|button| button.0 == *gamepad)
+            || gamepads.stick_of(&gamepad_axes, *gamepad) != Vec2::ZERO
+    });
+    if used.is_some() {
+        gamepads.driving = used;
+    }
+}
+
+// Losing the pad mid run would leave the player flying with nobody at the controls.
+// A replay plays itself, so there is nobody to lose.
+fn pause_on_disconnect(
+    gamepads: Res<Gamepads>,
+    playback: Option<Res<Playback>>,
+    mut state: ResMut<State<GameState>>,
+) {
+    if gamepads.driving_disconnected && playback.is_none() {
+        let _ = state.push(GameState::Paused);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::app::Events;
+    use bevy::input::gamepad::GamepadEventRaw;
+    use bevy::input::keyboard::KeyboardInput;
+    use bevy::input::ElementState;
+
+    use crate::logic::actions::ActionsPlugin;
+    use crate::logic::input::{DeviceInputPlugin, PlayerInput};
+    use crate::logic::replay::Replay;
+    use crate::testing;
+
+    const PAD: Gamepad = Gamepad(0);
+
+    // Just enough of the game to turn gamepad events into player input
+    fn headless_app() -> App {
+        let mut builder = testing::headless_app(GameConfig::default());
+        builder
+            .add_state(GameState::Playing)
+            .init_resource::<PlayerInput>()
+            .add_plugin(GamepadPlugin)
+            .add_plugin(ActionsPlugin)
+            .add_plugin(DeviceInputPlugin);
+        builder.app
+    }
+
+    // Sends the events the way gilrs would and runs one frame
+    fn send(app: &mut App, events: &[GamepadEventRaw]) {
+        let mut raw_events = app
+            .world
+            .get_resource_mut::<Events<GamepadEventRaw>>()
+            .unwrap();
+
+        for event in events {
+            raw_events.send(event.clone());
+        }
+
+        app.update();
+    }
+
+    fn player_input(app: &App) -> PlayerInput {
+        *app.world.get_resource::<PlayerInput>().unwrap()
+    }
+
+    fn connected(app: &App) -> Vec<Gamepad> {
+        app.world
+            .get_resource::<Gamepads>()
+            .unwrap()
+            .iter()
+            .collect()
+    }
+
+    fn stick(x: f32, y: f32) -> [GamepadEventRaw; 2] {
+        [
+            GamepadEventRaw(
+                PAD,
+                GamepadEventType::AxisChanged(GamepadAxisType::LeftStickX, x),
+            ),
+            GamepadEventRaw(
+                PAD,
+                GamepadEventType::AxisChanged(GamepadAxisType::LeftStickY, y),
+            ),
+        ]
+    }
+
+    fn button(button_type: GamepadButtonType, value: f32) -> GamepadEventRaw {
+        GamepadEventRaw(PAD, GamepadEventType::ButtonChanged(button_type, value))
+    }
+
+    fn connect(app: &mut App, gamepad: Gamepad) {
+        send(
+            app,
+            &[GamepadEventRaw(gamepad, GamepadEventType::Connected)],
+        );
+    }
+
+    #[test]
+    fn dead_zone_is_round_and_rescales_the_rest() {
+        assert_eq!(apply_dead_zone(Vec2::new(0.1, 0.1), 0.2), Vec2::ZERO);
+        assert!(apply_dead_zone(Vec2::new(0.6, 0.0), 0.2).abs_diff_eq(Vec2::new(0.5, 0.0), 1e-6));
+        assert!(apply_dead_zone(Vec2::new(0.0, -1.0), 0.2).abs_diff_eq(Vec2::new(0.0, -1.0), 1e-6));
+
+        // Past the edge in a corner, still no faster than full speed
+        let corner = apply_dead_zone(Vec2::new(1.0, 1.0), 0.2);
+        assert!((corner.length() - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn tracks_pads_as_they_are_plugged_in_and_out() {
+        let mut app = headless_app();
+        assert!(connected(&app).is_empty());
+
+        connect(&mut app, Gamepad(0));
+        connect(&mut app, Gamepad(1));
+        // Reconnecting doesn't count the pad twice
+        connect(&mut app, Gamepad(0));
+        assert_eq!(connected(&app), vec![Gamepad(0), Gamepad(1)]);
+
+        send(
+            &mut app,
+            &[GamepadEventRaw(Gamepad(0), GamepadEventType::Disconnected)],
+        );
+        assert_eq!(connected(&app), vec![Gamepad(1)]);
+    }
+
+    fn current_state(app: &App) -> GameState {
+        app.world
+            .get_resource::<State<GameState>>()
+            .unwrap()
+            .current()
+            .clone()
+    }
+
+    fn disconnect(app: &mut App, gamepad: Gamepad) {
+        send(
+            app,
+            &[GamepadEventRaw(gamepad, GamepadEventType::Disconnected)],
+        );
+        app.update();
+    }
+
+    #[test]
+    fn unplugging_the_pad_in_use_pauses_the_run() {
+        let mut app = headless_app();
+        connect(&mut app, PAD);
+        send(&mut app, &stick(1.0, 0.0));
+        assert_eq!(current_state(&app), GameState::Playing);
+
+        disconnect(&mut app, PAD);
+        assert_eq!(current_state(&app), GameState::Paused);
+    }
+
+    #[test]
+    fn unplugging_a_spare_pad_keeps_playing() {
+        let mut app = headless_app();
+        connect(&mut app, PAD);
+        connect(&mut app, Gamepad(1));
+        send(&mut app, &stick(1.0, 0.0));
+
+        disconnect(&mut app, Gamepad(1));
+        assert_eq!(current_state(&app), GameState::Playing);
+
+        // Nor one that was used before the player went back to the keyboard
+        send(&mut app, &stick(0.0, 0.0));
+        app.world
+            .get_resource_mut::<Events<KeyboardInput>>()
+            .unwrap()
+            .send(KeyboardInput {
+                scan_code: 0,
+                key_code: Some(KeyCode::W),
+                state: ElementState::Pressed,
+            });
+        app.update();
+        disconnect(&mut app, PAD);
+        assert_eq!(current_state(&app), GameState::Playing);
+    }
+
+    #[test]
+    fn unplugging_a_pad_during_a_replay_keeps_playing() {
+        let mut app = headless_app();
+        app.world
+            .insert_resource(Playback::new(Replay::new(0, 0), false));
+        connect(&mut app, PAD);
+        send(&mut app, &stick(1.0, 0.0));
+
+        disconnect(&mut app, PAD);
+        assert_eq!(current_state(&app), GameState::Playing);
+    }
+
+    #[test]
+    fn stick_speed_grows_past_the_dead_zone() {
+        let mut app = headless_app();
+        connect(&mut app, PAD);
+
+        // Inside the default dead zone of 0.2
+        send(&mut app, &stick(0.15, -0.1));
+        assert_eq!(player_input(&app).stick, (0, 0));
+
+        // Halfway between the dead zone and the edge
+        send(&mut app, &stick(0.6, 0.0));
+        assert_eq!(player_input(&app).stick, (64, 0));
+        assert!((player_input(&app).stick_vector().x - 0.5).abs() < 0.01);
+
+        send(&mut app, &stick(0.0, -1.0));
+        assert_eq!(player_input(&app).stick, (0, -127));
+    }
+
+    #[test]
+    fn stick_is_ignored_once_the_pad_is_gone() {
+        let mut app = headless_app();
+        connect(&mut app, PAD);
+        send(&mut app, &stick(1.0, 0.0));
+        assert_eq!(player_input(&app).stick, (127, 0));
+
+        send(
+            &mut app,
+            &[GamepadEventRaw(PAD, GamepadEventType::Disconnected)],
+        );
+        assert_eq!(player_input(&app).stick, (0, 0));
+    }
+
+    #[test]
+    fn buttons_teleport_and_fire() {
+        let mut app = headless_app();
+        connect(&mut app, PAD);
+
+        send(&mut app, &[button(GamepadButtonType::South, 1.0)]);
+        assert!(player_input(&app).teleport);
+
+        // Teleporting only happens on the tick the button goes down
+        app.update();
+        assert!(!player_input(&app).teleport);
+
+        send(&mut app, &[button(GamepadButtonType::RightTrigger, 1.0)]);
+        assert!(player_input(&app).fire);
+        app.update();
+        assert!(player_input(&app).fire);
+
+        send(&mut app, &[button(GamepadButtonType::RightTrigger, 0.0)]);
+        assert!(!player_input(&app).fire);
+    }
+
+    #[test]
+    fn buttons_on_unknown_pads_do_nothing() {
+        let mut app = headless_app();
+
+        send(&mut app, &[button(GamepadButtonType::South, 1.0)]);
+        assert!(!player_input(&app).teleport);
+    }
+
+    #[test]
+    fn stick_survives_a_replay() {
+        let input = PlayerInput {
+            up: true,
+            fire: true,
+            stick: (-127, 64),
+            ..PlayerInput::default()
+        };
+
+        assert!(PlayerInput::from_bits(input.to_bits()) == input);
+    }
+}
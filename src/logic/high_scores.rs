@@ -0,0 +1,349 @@
+// HIGH SCORE CODE
+
+use bevy::prelude::*;
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::storage;
+
+use super::difficulty::DifficultyPreset;
+use super::player::ControlScheme;
+use super::rng::GameRng;
+use super::score::Score;
+use super::state::GameState;
+
+// Bump this whenever HighScoreTable or HighScoreEntry changes shape
+const HIGH_SCORE_VERSION: u32 = 2;
+// Kept for each preset
+const MAX_HIGH_SCORES: usize = 10;
+
+// Keeps the best runs on disk between sessions.
+// Only added to windowed games, so bots and headless runs
+// don't fill the table with their scores.
+pub struct HighScorePlugin;
+
+impl Plugin for HighScorePlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<PlayerName>()
+            .insert_resource(HighScores::load(high_score_path()))
+            // The run has ended by the time we get to the game over screen,
+            // so the score can't change anymore.
+            .add_system_set(
+                SystemSet::on_enter(GameState::GameOver).with_system(record_high_score.system()),
+            );
+    }
+}
+
+// The name written next to the player's high scores
+pub struct PlayerName(pub String);
+
+impl Default for PlayerName {
+    fn default() -> Self {
+        let name = env::var("USER")
+            .or_else(|_| env::var("USERNAME"))
+            .unwrap_or_else(|_| "Player".to_string());
+
+        PlayerName(name)
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct HighScoreEntry {
+    pub name: String,
+    pub score: i32,
+    // Local date the run ended on, as YYYY-MM-DD
+    pub date: String,
+    // The run's own seed, starting the game with it plays the same run
+    pub seed: u64,
+    pub mode: String,
+    // Every score before presets was set on normal
+    #[serde(default = "normal_preset")]
+    pub preset: String,
+}
+
+fn normal_preset() -> String {
+    DifficultyPreset::Normal.name().to_string()
+}
+
+#[derive(Serialize, Deserialize)]
+struct HighScoreTable {
+    version: u32,
+    entries: Vec<HighScoreEntry>,
+}
+
+pub struct HighScores {
+    // Where the table is saved, None if there is nowhere to save it
+    path: Option<PathBuf>,
+    // Sorted from the best score to the worst, every preset mixed together
+    entries: Vec<HighScoreEntry>,
+}
+
+impl HighScores {
+    fn load(path: Option<PathBuf>) -> Self {
+        let entries = match &path {
+            Some(path) => read_table(path),
+            None => {
+                println!("Could not find a data directory, high scores won't be saved.");
+                Vec::new()
+            }
+        };
+
+        HighScores { path, entries }
+    }
+
+    // The best score set on the preset
+    pub fn best(&self, preset: DifficultyPreset) -> Option<i32> {
+        self.entries_for(preset.name())
+            .next()
+            .map(|entry| entry.score)
+    }
+
+    fn entries_for<'a>(&'a self, preset: &'a str) -> impl Iterator<Item = &'a HighScoreEntry> {
+        self.entries
+            .iter()
+            .filter(move |entry| entry.preset == preset)
+    }
+
+    // Adds the entry if it makes the table for its preset,
+    // returning its place among that preset's scores (0 is the best)
+    fn insert(&mut self, entry: HighScoreEntry) -> Option<usize> {
+        // Ties go below the older entries
+        let place = self
+            .entries_for(&entry.preset)
+            .take_while(|existing| entry.score <= existing.score)
+            .count();
+
+        if place >= MAX_HIGH_SCORES {
+            return None;
+        }
+
+        let index = self
+            .entries
+            .iter()
+            .position(|existing| entry.score > existing.score)
+            .unwrap_or(self.entries.len());
+        let preset = entry.preset.clone();
+        self.entries.insert(index, entry);
+
+        // Drop whatever this pushed off the bottom of the preset's table
+        let mut kept = 0;
+        self.entries.retain(|existing| {
+            if existing.preset != preset {
+                return true;
+            }
+
+            kept += 1;
+            kept <= MAX_HIGH_SCORES
+        });
+
+        Some(place)
+    }
+
+    fn save(&self) -> io::Result<()> {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+
+        let table = HighScoreTable {
+            version: HIGH_SCORE_VERSION,
+            entries: self.entries.clone(),
+        };
+
+        let contents = ron::ser::to_string_pretty(&table, Default::default())
+            .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))?;
+
+        storage::write_atomically(path, &contents)
+    }
+}
+
+fn high_score_path() -> Option<PathBuf> {
+    storage::data_dir().map(|directory| directory.join("high_scores.ron"))
+}
+
+// A missing table is an empty one. A table we can't understand is moved
+// out of the way instead of being overwritten, so no scores are lost.
+fn read_table(path: &Path) -> Vec<HighScoreEntry> {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => return Vec::new(),
+        Err(error) => {
+            println!(
+                "Could not read high scores at {}: {}",
+                path.display(),
+                error
+            );
+            return Vec::new();
+        }
+    };
+
+    // Version 1 only lacks the preset, which reads as normal
+    let problem = match ron::de::from_str::<HighScoreTable>(&contents) {
+        Ok(table) if table.version == HIGH_SCORE_VERSION || table.version == 1 => {
+            return table.entries
+        }
+        Ok(table) => format!(
+            "it is version {}, expected {}",
+            table.version, HIGH_SCORE_VERSION
+        ),
+        Err(error) => error.to_string(),
+    };
+
+    let backup_path = path.with_extension("ron.bak");
+    println!(
+        "Could not load high scores at {} ({}), moving them to {}.",
+        path.display(),
+        problem,
+        backup_path.display()
+    );
+
+    if let Err(error) = fs::rename(path, &backup_path) {
+        println!("Could not move the old high scores: {}", error);
+    }
+
+    Vec::new()
+}
+
+fn record_high_score(
+    score: Res<Score>,
+    rng: Res<GameRng>,
+    control_scheme: Res<ControlScheme>,
+    preset: Res<DifficultyPreset>,
+    player_name: Res<PlayerName>,
+    mut high_scores: ResMut<HighScores>,
+) {
+    let entry = HighScoreEntry {
+        name: player_name.0.clone(),
+        score: score.value,
+        date: Local::now().format("%Y-%m-%d").to_string(),
+        seed: rng.run_seed(),
+        mode: control_scheme.name().to_string(),
+        preset: preset.name().to_string(),
+    };
+
+    if let Some(place) = high_scores.insert(entry) {
+        println!(
+            "New high score! {} is number {} on {}.",
+            score.value,
+            place + 1,
+            preset.name()
+        );
+
+        if let Err(error) = high_scores.save() {
+            println!("Could not save high scores: {}", error);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(name: &str, score: i32, preset: DifficultyPreset) -> HighScoreEntry {
+        HighScoreEntry {
+            name: name.to_string(),
+            score,
+            date: "2021-06-01".to_string(),
+            seed: 0,
+            mode: ControlScheme::Arcade.name().to_string(),
+            preset: preset.name().to_string(),
+        }
+    }
+
+    fn names(high_scores: &HighScores, preset: DifficultyPreset) -> Vec<String> {
+        high_scores
+            .entries_for(preset.name())
+            .map(|entry| entry.name.clone())
+            .collect()
+    }
+
+    fn empty() -> HighScores {
+        HighScores {
+            path: None,
+            entries: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn full_table_keeps_the_best() {
+        let mut high_scores = empty();
+        for score in 1..=MAX_HIGH_SCORES as i32 {
+            high_scores.insert(entry(
+                &score.to_string(),
+                score * 10,
+                DifficultyPreset::Normal,
+            ));
+        }
+
+        // Worse than everything in a full table
+        assert_eq!(
+            high_scores.insert(entry("low", 5, DifficultyPreset::Normal)),
+            None
+        );
+
+        // Somewhere in the middle pushes the worst one out
+        assert_eq!(
+            high_scores.insert(entry("middle", 55, DifficultyPreset::Normal)),
+            Some(5)
+        );
+        let names = names(&high_scores, DifficultyPreset::Normal);
+        assert_eq!(names.len(), MAX_HIGH_SCORES);
+        assert_eq!(names[5], "middle");
+        assert_eq!(names.last().unwrap(), "2");
+        assert_eq!(high_scores.best(DifficultyPreset::Normal), Some(100));
+    }
+
+    #[test]
+    fn ties_go_below_older_scores() {
+        let mut high_scores = empty();
+        high_scores.insert(entry("first", 50, DifficultyPreset::Normal));
+        high_scores.insert(entry("better", 70, DifficultyPreset::Normal));
+
+        assert_eq!(
+            high_scores.insert(entry("second", 50, DifficultyPreset::Normal)),
+            Some(2)
+        );
+        assert_eq!(
+            names(&high_scores, DifficultyPreset::Normal),
+            vec!["better", "first", "second"]
+        );
+
+        // A tie with the last place of a full table doesn't make it
+        let mut high_scores = empty();
+        for _ in 0..MAX_HIGH_SCORES {
+            high_scores.insert(entry("old", 50, DifficultyPreset::Normal));
+        }
+        assert_eq!(
+            high_scores.insert(entry("new", 50, DifficultyPreset::Normal)),
+            None
+        );
+    }
+
+    #[test]
+    fn presets_keep_tables_of_their_own() {
+        let mut high_scores = empty();
+        high_scores.insert(entry("easy", 1, DifficultyPreset::Easy));
+        for score in 0..MAX_HIGH_SCORES as i32 + 3 {
+            high_scores.insert(entry("hard", 100 + score, DifficultyPreset::Hard));
+        }
+
+        // A full table of better hard scores pushes no easy ones out
+        assert_eq!(names(&high_scores, DifficultyPreset::Easy), vec!["easy"]);
+        assert_eq!(
+            names(&high_scores, DifficultyPreset::Hard).len(),
+            MAX_HIGH_SCORES
+        );
+
+        // And a low easy score still makes it into the easy table
+        assert_eq!(
+            high_scores.insert(entry("easier", 0, DifficultyPreset::Easy)),
+            Some(1)
+        );
+        assert_eq!(high_scores.best(DifficultyPreset::Easy), Some(1));
+        assert_eq!(high_scores.best(DifficultyPreset::Normal), None);
+    }
+}
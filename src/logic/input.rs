@@ -0,0 +1,114 @@
+// INPUT CODE
+
+use bevy::prelude::*;
+
+use super::actions::{Action, InputActions};
+use super::clock::GameClock;
+use super::gamepad::Gamepads;
+use super::state::GameState;
+use super::GameSystem;
+
+// Reads the player's input from the keyboard, mouse and any gamepads,
+// through the key bindings
+pub struct DeviceInputPlugin;
+
+impl Plugin for DeviceInputPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_system_set(
+            SystemSet::on_update(GameState::Playing)
+                .with_system(read_device_input.system().label(GameSystem::Input)),
+        );
+    }
+}
+
+// How many steps the stick has on each side of the center.
+// The stick is stored in whole steps so replays keep exactly what was played.
+const STICK_STEPS: f32 = 127.0;
+
+// What the player wants to do this tick.
+// Gameplay systems read this instead of the keyboard, so a run can be
+// played back from a replay file exactly as it was recorded.
+#[derive(Default, Clone, Copy, PartialEq, Eq)]
+pub struct PlayerInput {
+    pub up: bool,
+    pub down: bool,
+    pub left: bool,
+    pub right: bool,
+    // Only true on the tick the teleport key was pressed
+    pub teleport: bool,
+    // True for as long as the fire key is held
+    pub fire: bool,
+    // How far the analog stick is pushed past its dead zone,
+    // from -127 to 127 on each axis
+    pub stick: (i8, i8),
+}
+
+impl PlayerInput {
+    // Packs the input into a number for replay files.
+    // The buttons are the low byte and each stick axis gets a byte above it.
+    pub fn to_bits(self) -> u32 {
+        self.up as u32
+            | (self.down as u32) << 1
+            | (self.left as u32) << 2
+            | (self.right as u32) << 3
+            | (self.teleport as u32) << 4
+            | (self.fire as u32) << 5
+            | (self.stick.0 as u8 as u32) << 8
+            | (self.stick.1 as u8 as u32) << 16
+    }
+
+    pub fn from_bits(bits: u32) -> Self {
+        PlayerInput {
+            up: bits & 1 != 0,
+            down: bits & 1 << 1 != 0,
+            left: bits & 1 << 2 != 0,
+            right: bits & 1 << 3 != 0,
+            teleport: bits & 1 << 4 != 0,
+            fire: bits & 1 << 5 != 0,
+            stick: ((bits >> 8) as u8 as i8, (bits >> 16) as u8 as i8),
+        }
+    }
+
+    // Rounds a stick position to the nearest step
+    pub fn quantize_stick(stick: Vec2) -> (i8, i8) {
+        let steps = (stick.clamp_length_max(1.0) * STICK_STEPS).round();
+        (steps.x as i8, steps.y as i8)
+    }
+
+    // The stick as a vector no longer than 1
+    pub fn stick_vector(self) -> Vec2 {
+        Vec2::new(self.stick.0 as f32, self.stick.1 as f32) / STICK_STEPS
+    }
+}
+
+// NOTE
+// The teleport key can be pressed on a frame where the simulation
+// doesn't tick, so remember it until the next tick comes around.
+fn read_device_input(
+    input_actions: Res<InputActions>,
+    gamepad_axes: Res<Axis<GamepadAxis>>,
+    gamepads: Res<Gamepads>,
+    clock: Res<GameClock>,
+    mut player_input: ResMut<PlayerInput>,
+    mut teleport_pressed: Local<bool>,
+) {
+    *teleport_pressed |= input_actions.just_pressed(Action::Teleport);
+
+    if !clock.ticked() {
+        *player_input = PlayerInput::default();
+        return;
+    }
+
+    *player_input = PlayerInput {
+        up: input_actions.pressed(Action::MoveUp),
+        down: input_actions.pressed(Action::MoveDown),
+        left: input_actions.pressed(Action::MoveLeft),
+        right: input_actions.pressed(Action::MoveRight),
+        teleport: *teleport_pressed,
+        fire: input_actions.pressed(Action::Fire),
+        // The stick is analog, so it is read straight from the pads
+        stick: PlayerInput::quantize_stick(gamepads.left_stick(&gamepad_axes)),
+    };
+
+    *teleport_pressed = false;
+}
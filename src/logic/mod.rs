@@ -1,5 +1,138 @@
+use bevy::app::{PluginGroup, PluginGroupBuilder};
+use bevy::ecs::schedule::SystemLabel;
+use bevy::math::{Vec2, Vec3};
+
+use crate::config::GameConfig;
+
+pub mod actions;
 pub mod blocks;
+pub mod clock;
+pub mod collision;
+pub mod difficulty;
+pub mod gamepad;
+pub mod high_scores;
+pub mod input;
 pub mod player;
+pub mod projectiles;
+pub mod replay;
 pub mod reset_game;
+pub mod respawn;
+pub mod rng;
+pub mod score;
+pub mod spatial_hash;
 /// Block Game logic
 pub mod spawning;
+pub mod state;
+pub mod teleport;
+
+// Everything the game needs to simulate a run.
+// None of these plugins touch the window, renderer or audio,
+// so they can run under MinimalPlugins as well as DefaultPlugins.
+//
+// Where the player's input comes from is up to the app, it needs to add
+// either the device input plugin or the replay playback plugin.
+//
+// The input plugin from bevy has to be added too, for the keyboard and gamepads.
+pub struct LogicPlugins;
+
+impl PluginGroup for LogicPlugins {
+    fn build(&mut self, group: &mut PluginGroupBuilder) {
+        group
+            .add(clock::ClockPlugin)
+            .add(rng::RngPlugin)
+            .add(state::GameStatePlugin)
+            .add(gamepad::GamepadPlugin)
+            .add(actions::ActionsPlugin)
+            .add(spawning::SpawningPlugin)
+            .add(difficulty::DifficultyPlugin)
+            .add(player::PlayerPlugin)
+            .add(respawn::RespawnPlugin)
+            .add(teleport::TeleportPlugin)
+            .add(blocks::BlocksPlugin)
+            .add(projectiles::ProjectilesPlugin)
+            .add(spatial_hash::SpatialHashPlugin)
+            .add(collision::CollisionPlugin)
+            .add(reset_game::ResetGamePlugin)
+            .add(score::ScorePlugin);
+    }
+}
+
+// The order the simulation runs in during a tick.
+// Systems that read or change what another system in here changes
+// need a fixed place in this order, otherwise replays can desync.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, SystemLabel)]
+pub enum GameSystem {
+    // Works out how hard this tick is, before anything spawns or moves
+    Difficulty,
+    Input,
+    Movement,
+    // Jumps the player once everything else has moved
+    Teleport,
+    // Finds overlaps and sends collision events
+    Collision,
+    // Reacts to the collision events
+    CollisionResponse,
+}
+
+// Settles the control scheme, difficulty preset and seed as a run starts, replays set
+// the first two from the recording. Anything that reads them when a run starts goes after this.
+// Added by the game state and RNG plugins, so it is there for every run.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, SystemLabel)]
+pub struct RunSettings;
+
+// Moves something that has gone fully off one edge of the screen
+// back in from the opposite edge
+pub fn wrap_around_screen(translation: &mut Vec3, size: Vec2, config: &GameConfig) {
+    if translation.x > config.half_width() + size.x {
+        translation.x = -config.half_width();
+    }
+
+    if translation.x < -config.half_width() - size.x {
+        translation.x = config.half_width();
+    }
+
+    if translation.y > config.half_height() + size.y {
+        translation.y = -config.half_height();
+    }
+
+    if translation.y < -config.half_height() - size.y {
+        translation.y = config.half_height();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::prelude::*;
+
+    use super::blocks::Block;
+    use super::input::DeviceInputPlugin;
+    use super::score::SurvivalTime;
+    use super::state::GameState;
+    use crate::config::GameConfig;
+    use crate::testing;
+
+    // Ten seconds of the whole game with nobody at the controls
+    #[test]
+    fn logic_runs_headless() {
+        let mut builder = testing::game_app(GameConfig::default(), 42);
+        builder.add_plugin(DeviceInputPlugin);
+        let mut app = builder.app;
+
+        for _ in 0..600 {
+            app.update();
+        }
+
+        let state = app.world.get_resource::<State<GameState>>().unwrap();
+        assert!(matches!(
+            state.current(),
+            GameState::Playing | GameState::GameOver
+        ));
+
+        // The run starts on the first update, once loading is done
+        let survival_time = app.world.get_resource::<SurvivalTime>().unwrap();
+        assert!(survival_time.ticks > 0 && survival_time.ticks <= 600);
+
+        let mut blocks = app.world.query::<&Block>();
+        assert!(blocks.iter(&app.world).next().is_some());
+    }
+}
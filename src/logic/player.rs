@@ -1,18 +1,47 @@
 // PLAYER CODE
 
-use crate::{Collidable, WINDOWHEIGHT, WINDOWWIDTH};
+use crate::config::GameConfig;
+use crate::Dimensions;
 
+use bevy::math::Mat2;
 use bevy::prelude::*;
-use bevy::sprite::collide_aabb::collide;
+use serde::{Deserialize, Serialize};
+
+use super::blocks::{Block, BlockTier};
+use super::clock::GameClock;
+use super::collision::{Collider, CollisionEvent, CollisionKind};
+use super::difficulty::DifficultyPreset;
+use super::input::PlayerInput;
+use super::respawn::Respawning;
+use super::score::SurvivalTime;
+use super::state::GameState;
+use super::{wrap_around_screen, GameSystem};
 
 pub struct PlayerPlugin;
 
 impl Plugin for PlayerPlugin {
     fn build(&self, app: &mut AppBuilder) {
+        // The player is spawned when a run starts, see the reset game module
         app.add_event::<PlayerDeathEvent>()
-            .add_startup_system(spawn_startup_player.system())
-            .add_system(move_player.system())
-            .add_system(player_collision_system.system());
+            .init_resource::<PlayerInput>()
+            .init_resource::<ControlScheme>()
+            .init_resource::<Lives>()
+            .add_system_set(
+                SystemSet::on_update(GameState::Playing)
+                    .with_system(
+                        move_player
+                            .system()
+                            .label(GameSystem::Movement)
+                            .after(GameSystem::Input),
+                    )
+                    .with_system(
+                        kill_player_on_hit
+                            .system()
+                            .label(GameSystem::CollisionResponse)
+                            .after(GameSystem::Collision),
+                    )
+                    .with_system(wear_off_invulnerability.system()),
+            );
     }
 }
 
@@ -20,128 +49,388 @@ impl Plugin for PlayerPlugin {
 pub struct Player {
     pub velocity: f32,
     pub teleport_distance: f32,
+    // The way the player last moved, or the way the ship points with
+    // the classic controls. Bullets are fired this way.
+    pub facing: Vec2,
+    // in pixels per second, only the classic controls keep moving on their own
+    pub momentum: Vec2,
 }
 
-fn spawn_startup_player(
-    mut commands: Commands,
-    asset_server: Res<AssetServer>,
-    mut materials: ResMut<Assets<ColorMaterial>>,
-) {
-    spawn_player(&mut commands, &asset_server, &mut materials);
+// How many more hits the player can take this run, the last one ends it
+pub struct Lives(pub u32);
+
+impl FromWorld for Lives {
+    fn from_world(world: &mut World) -> Self {
+        let config = world
+            .get_resource::<GameConfig>()
+            .expect("The game config is added before the plugins.");
+
+        Lives(config.lives)
+    }
 }
 
-pub fn spawn_player(
-    commands: &mut Commands,
-    asset_server: &Res<AssetServer>,
-    materials: &mut ResMut<Assets<ColorMaterial>>,
-) {
-    let sprite_size_x = 40.0;
-    let sprite_size_y = 40.0;
-    let texture_handle = asset_server.load("textures/block_3.png");
+// Blocks go straight through the player until this runs out.
+// The graphics plugins make the player blink in the meantime.
+pub struct Invulnerable(pub Timer);
 
-    commands
-        .spawn_bundle(SpriteBundle {
-            material: materials.add(texture_handle.into()),
-            transform: Transform::from_xyz(0.0, 0.0, 1.0),
-            sprite: Sprite::new(Vec2::new(sprite_size_x, sprite_size_y)),
-            ..Default::default()
-        })
-        .insert(Player {
-            velocity: 300.0,
-            teleport_distance: 70.0,
-        })
-        .insert(Collidable);
+// How the player moves, picked before each run
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ControlScheme {
+    // Move along the held directions at a constant speed
+    Arcade,
+    // Turn, thrust and drift like the original Asteroids ship
+    Classic,
 }
 
-fn move_player(
-    keyboard_input: Res<Input<KeyCode>>,
-    mut player_query: Query<(&Player, &mut Transform, &Sprite)>,
-    time: Res<Time>,
-) {
-    if let Ok((player, mut transform, sprite)) = player_query.single_mut() {
-        // Get input from the keyboard (WASD)
-        let up: bool = keyboard_input.pressed(KeyCode::W) || keyboard_input.pressed(KeyCode::Up);
-        let down: bool =
-            keyboard_input.pressed(KeyCode::S) || keyboard_input.pressed(KeyCode::Down);
-        let left: bool =
-            keyboard_input.pressed(KeyCode::A) || keyboard_input.pressed(KeyCode::Left);
-        let right: bool =
-            keyboard_input.pressed(KeyCode::D) || keyboard_input.pressed(KeyCode::Right);
-
-        // If left is pressed than it will be -1, right 1, both they cancel out.
-        let x_axis: i8 = -(left as i8) + right as i8;
-        let y_axis: i8 = -(down as i8) + up as i8;
-        let move_delta: Vec2 = Vec2::new(x_axis as f32, y_axis as f32);
-
-        // move the player
-        let delta_time = time.delta_seconds();
-        transform.translation.x += move_delta.x * player.velocity * delta_time;
-        transform.translation.y += move_delta.y * player.velocity * delta_time;
+impl Default for ControlScheme {
+    fn default() -> Self {
+        ControlScheme::Arcade
+    }
+}
 
-        // Wrap the player if they go off screen
-        if transform.translation.x > WINDOWWIDTH / 2.0 + sprite.size.x {
-            transform.translation.x = -WINDOWWIDTH / 2.0;
+impl ControlScheme {
+    pub fn name(self) -> &'static str {
+        match self {
+            ControlScheme::Arcade => "arcade",
+            ControlScheme::Classic => "classic",
         }
+    }
 
-        if transform.translation.x < -WINDOWWIDTH / 2.0 - sprite.size.x {
-            transform.translation.x = WINDOWWIDTH / 2.0;
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "arcade" => Some(ControlScheme::Arcade),
+            "classic" => Some(ControlScheme::Classic),
+            _ => None,
         }
+    }
 
-        if transform.translation.y > WINDOWHEIGHT / 2.0 + sprite.size.y {
-            transform.translation.y = -WINDOWHEIGHT / 2.0;
+    pub fn other(self) -> Self {
+        match self {
+            ControlScheme::Arcade => ControlScheme::Classic,
+            ControlScheme::Classic => ControlScheme::Arcade,
         }
+    }
+}
+
+impl Player {
+    // A player standing still and facing up, as they are when a run starts
+    pub fn new(config: &GameConfig, preset: DifficultyPreset) -> Self {
+        let settings = preset.settings();
 
-        if transform.translation.y < -WINDOWHEIGHT / 2.0 - sprite.size.y {
-            transform.translation.y = WINDOWHEIGHT / 2.0;
+        Player {
+            velocity: config.player_velocity * settings.player_speed,
+            teleport_distance: config.teleport_distance * settings.teleport_distance,
+            facing: Vec2::Y,
+            momentum: Vec2::ZERO,
         }
+    }
+}
 
-        // teleport the player if they press space
-        if keyboard_input.just_pressed(KeyCode::Space) {
-            if y_axis == -1 {
-                transform.translation.y -= player.teleport_distance;
-            }
+// The sprite is attached by the graphics plugins when there is a window.
+// The respawn module moves the player somewhere safe before they first move.
+pub fn spawn_player(
+    commands: &mut Commands,
+    config: &GameConfig,
+    preset: DifficultyPreset,
+) -> Entity {
+    let size = Vec2::new(config.player_size.0, config.player_size.1);
 
-            if y_axis == 1 {
-                transform.translation.y += player.teleport_distance;
-            }
+    commands
+        .spawn()
+        .insert(Transform::from_xyz(0.0, 0.0, 1.0))
+        .insert(Dimensions(size))
+        .insert(Player::new(config, preset))
+        .insert(Collider::aabb(size).with_inset(config.player_hitbox_inset))
+        .insert(Respawning)
+        .id()
+}
 
-            if x_axis == 1 {
-                transform.translation.x += player.teleport_distance;
-            }
+fn move_player(
+    player_input: Res<PlayerInput>,
+    control_scheme: Res<ControlScheme>,
+    mut player_query: Query<(&mut Player, &mut Transform, &Dimensions)>,
+    clock: Res<GameClock>,
+    config: Res<GameConfig>,
+) {
+    if let Ok((mut player, mut transform, dimensions)) = player_query.single_mut() {
+        let delta_time = clock.delta_seconds();
 
-            if x_axis == -1 {
-                transform.translation.x -= player.teleport_distance;
+        match *control_scheme {
+            ControlScheme::Arcade => {
+                arcade_movement(&mut player, &mut transform, &player_input, delta_time)
             }
+            ControlScheme::Classic => classic_movement(
+                &mut player,
+                &mut transform,
+                &player_input,
+                &config,
+                delta_time,
+            ),
         }
+
+        // Wrap the player if they go off screen
+        wrap_around_screen(&mut transform.translation, dimensions.0, &config);
+    }
+}
+
+// Moves at a constant speed along the axes that are held
+fn arcade_movement(
+    player: &mut Player,
+    transform: &mut Transform,
+    player_input: &PlayerInput,
+    delta_time: f32,
+) {
+    let PlayerInput {
+        up,
+        down,
+        left,
+        right,
+        ..
+    } = *player_input;
+
+    // If left is pressed than it will be -1, right 1, both they cancel out.
+    let x_axis: i8 = -(left as i8) + right as i8;
+    let y_axis: i8 = -(down as i8) + up as i8;
+    let key_delta: Vec2 = Vec2::new(x_axis as f32, y_axis as f32);
+
+    // The keys win over the stick, which moves slower the less it is pushed
+    let stick = player_input.stick_vector();
+    let move_delta = if key_delta != Vec2::ZERO {
+        key_delta
+    } else {
+        stick
+    };
+
+    if move_delta != Vec2::ZERO {
+        player.facing = move_delta.normalize();
     }
+
+    // move the player
+    transform.translation.x += move_delta.x * player.velocity * delta_time;
+    transform.translation.y += move_delta.y * player.velocity * delta_time;
 }
 
-pub struct PlayerDeathEvent;
+// Left and right turn the ship, up thrusts it forward.
+// The ship keeps drifting after letting go until drag slows it down.
+fn classic_movement(
+    player: &mut Player,
+    transform: &mut Transform,
+    player_input: &PlayerInput,
+    config: &GameConfig,
+    delta_time: f32,
+) {
+    // Turning left is counterclockwise.
+    // The stick turns and thrusts as hard as it is pushed, the keys win over it.
+    let stick = player_input.stick_vector();
+    let key_turn = (player_input.left as i8 - player_input.right as i8) as f32;
+    let turn = if key_turn != 0.0 { key_turn } else { -stick.x };
+    let thrust = if player_input.up {
+        1.0
+    } else {
+        stick.y.max(0.0)
+    };
+    let turn_angle = turn * config.ship_turn_speed.to_radians() * delta_time;
+
+    // Normalized every tick so rounding errors don't build up over a long run
+    player.facing = (Mat2::from_angle(turn_angle) * player.facing).normalize();
+
+    player.momentum += player.facing * thrust * config.ship_thrust * delta_time;
 
-// simple, player collides with block system
-fn player_collision_system(
+    player.momentum *= (1.0 - config.ship_drag * delta_time).max(0.0);
+    player.momentum = player.momentum.clamp_length_max(config.ship_max_speed);
+
+    transform.translation += (player.momentum * delta_time).extend(0.0);
+
+    // The sprite is drawn facing up
+    transform.rotation = Quat::from_rotation_z(Vec2::Y.angle_between(player.facing));
+}
+
+// Sent once when the player runs out of lives, with what killed them
+#[derive(Debug, Clone, Copy)]
+pub struct PlayerDeathEvent {
+    pub block: Entity,
+    pub tier: BlockTier,
+    // Where the player was when they were hit
+    pub position: Vec2,
+    // Seconds since the run started, the same time the score counts
+    pub time_survived: f32,
+}
+
+// Touching a block costs a life, no matter how many blocks are touched at once.
+// The player comes back invulnerable for a while, or dies on their last life.
+#[allow(clippy::too_many_arguments)]
+fn kill_player_on_hit(
     mut commands: Commands,
-    mut player_query: Query<(Entity, &Sprite, &Transform), With<Player>>,
-    collider_query: Query<&Transform, (With<Collidable>, Without<Player>)>,
+    mut collision_event: EventReader<CollisionEvent>,
     mut player_death_event: EventWriter<PlayerDeathEvent>,
+    player_query: Query<(&Transform, Option<&Invulnerable>), With<Player>>,
+    block_query: Query<&Block>,
+    mut lives: ResMut<Lives>,
+    survival_time: Res<SurvivalTime>,
+    preset: Res<DifficultyPreset>,
+    config: Res<GameConfig>,
 ) {
-    if let Ok((player_entity, sprite, player_transform)) = player_query.single_mut() {
-        let player_size = sprite.size;
-
-        for transform in collider_query.iter() {
-            let collision = collide(
-                player_transform.translation,
-                player_size,
-                transform.translation,
-                sprite.size,
-            );
+    // The events are sorted, so the oldest block touching the player is blamed for the hit
+    let hit = match collision_event
+        .iter()
+        .find(|collision| collision.kind == CollisionKind::PlayerBlock)
+    {
+        Some(hit) => *hit,
+        None => return,
+    };
 
-            if let Some(_collision) = collision {
-                // Remove the player if they collide with a block
-                commands.entity(player_entity).despawn();
+    let (transform, invulnerable) = match player_query.get(hit.a) {
+        Ok(player) => player,
+        Err(_) => return,
+    };
 
-                player_death_event.send(PlayerDeathEvent);
-            }
+    if invulnerable.is_some() {
+        return;
+    }
+
+    commands.entity(hit.a).despawn();
+    lives.0 = lives.0.saturating_sub(1);
+
+    if lives.0 == 0 {
+        let tier = block_query
+            .get(hit.b)
+            .map(|block| block.tier)
+            .expect("Only blocks collide with the player.");
+
+        player_death_event.send(PlayerDeathEvent {
+            block: hit.b,
+            tier,
+            position: transform.translation.truncate(),
+            time_survived: survival_time.seconds(),
+        });
+    } else {
+        let player = spawn_player(&mut commands, &config, *preset);
+        commands
+            .entity(player)
+            .insert(Invulnerable(Timer::from_seconds(
+                config.respawn_invulnerability,
+                false,
+            )));
+    }
+}
+
+fn wear_off_invulnerability(
+    mut commands: Commands,
+    mut invulnerable_query: Query<(Entity, &mut Invulnerable)>,
+    clock: Res<GameClock>,
+) {
+    for (entity, mut invulnerable) in invulnerable_query.iter_mut() {
+        if invulnerable.0.tick(clock.delta_duration()).finished() {
+            commands.entity(entity).remove::<Invulnerable>();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::app::{Events, ManualEventReader};
+
+    use crate::testing;
+
+    fn headless_app(lives: u32) -> App {
+        let mut builder = testing::headless_app(GameConfig::default());
+        builder
+            .add_event::<CollisionEvent>()
+            .add_event::<PlayerDeathEvent>()
+            .insert_resource(Lives(lives))
+            .init_resource::<SurvivalTime>()
+            .init_resource::<DifficultyPreset>()
+            .add_system(kill_player_on_hit.system());
+        builder.app
+    }
+
+    fn spawn_blocks(app: &mut App, count: usize) -> Vec<Entity> {
+        let config = GameConfig::default();
+        (0..count)
+            .map(|_| {
+                app.world
+                    .spawn()
+                    .insert(Block::new(Vec2::X, BlockTier::Large, None, &config))
+                    .id()
+            })
+            .collect()
+    }
+
+    fn players(app: &mut App) -> Vec<(Entity, bool)> {
+        let mut player_query = app
+            .world
+            .query::<(Entity, &Player, Option<&Invulnerable>)>();
+        player_query
+            .iter(&app.world)
+            .map(|(entity, _player, invulnerable)| (entity, invulnerable.is_some()))
+            .collect()
+    }
+
+    // Every block touches the player on the same tick
+    fn hit(app: &mut App, player: Entity, blocks: &[Entity]) {
+        let mut collision_events = app
+            .world
+            .get_resource_mut::<Events<CollisionEvent>>()
+            .unwrap();
+
+        for block in blocks {
+            collision_events.send(CollisionEvent {
+                a: player,
+                b: *block,
+                kind: CollisionKind::PlayerBlock,
+            });
         }
+
+        app.update();
+    }
+
+    #[test]
+    fn many_blocks_on_one_tick_cost_one_life() {
+        let mut app = headless_app(2);
+        let player = app
+            .world
+            .spawn()
+            .insert(Player::new(
+                &GameConfig::default(),
+                DifficultyPreset::Normal,
+            ))
+            .insert(Transform::default())
+            .id();
+        let blocks = spawn_blocks(&mut app, 3);
+        let mut deaths = ManualEventReader::<PlayerDeathEvent>::default();
+
+        hit(&mut app, player, &blocks);
+        assert_eq!(app.world.get_resource::<Lives>().unwrap().0, 1);
+
+        // Back again straight away, and safe for a while
+        let respawned = players(&mut app);
+        assert_eq!(respawned.len(), 1);
+        let (player, invulnerable) = respawned[0];
+        assert!(invulnerable);
+
+        let death_events = app
+            .world
+            .get_resource::<Events<PlayerDeathEvent>>()
+            .unwrap();
+        assert_eq!(deaths.iter(death_events).count(), 0);
+
+        // Being hit while invulnerable costs nothing
+        hit(&mut app, player, &blocks);
+        assert_eq!(app.world.get_resource::<Lives>().unwrap().0, 1);
+
+        app.world.entity_mut(player).remove::<Invulnerable>();
+        hit(&mut app, player, &blocks);
+        assert_eq!(app.world.get_resource::<Lives>().unwrap().0, 0);
+        assert!(players(&mut app).is_empty());
+
+        // The last life ends the run once, blaming the first block
+        let death_events = app
+            .world
+            .get_resource::<Events<PlayerDeathEvent>>()
+            .unwrap();
+        let deaths: Vec<&PlayerDeathEvent> = deaths.iter(death_events).collect();
+        assert_eq!(deaths.len(), 1);
+        assert_eq!(deaths[0].block, blocks[0]);
     }
 }
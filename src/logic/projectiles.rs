@@ -0,0 +1,189 @@
+// PROJECTILES CODE
+
+use bevy::prelude::*;
+
+use crate::config::GameConfig;
+use crate::Dimensions;
+
+use super::blocks::{Block, BlockDestroyedEvent, BlockTier};
+use super::clock::GameClock;
+use super::collision::{Collider, CollisionEvent, CollisionKind};
+use super::input::PlayerInput;
+use super::player::Player;
+use super::state::GameState;
+use super::{wrap_around_screen, GameSystem};
+
+pub struct ProjectilesPlugin;
+
+impl Plugin for ProjectilesPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<FireCooldown>()
+            .init_resource::<BulletsFired>()
+            .add_system_set(
+                SystemSet::on_enter(GameState::Playing).with_system(clear_bullets.system()),
+            )
+            .add_system_set(
+                SystemSet::on_update(GameState::Playing)
+                    // Fired from where the player ends up this tick
+                    .with_system(fire_bullets.system().after(GameSystem::Teleport))
+                    .with_system(move_bullets.system().label(GameSystem::Movement))
+                    .with_system(
+                        destroy_shot_blocks
+                            .system()
+                            .label(GameSystem::CollisionResponse)
+                            .after(GameSystem::Collision),
+                    )
+                    .with_system(
+                        despawn_spent_bullets
+                            .system()
+                            .after(GameSystem::CollisionResponse),
+                    ),
+            );
+    }
+}
+
+pub struct Bullet {
+    velocity: Vec2,
+    // Seconds left before the bullet disappears, a bullet that hits something has none left
+    lifetime: f32,
+    // Counts up with every bullet fired, the lowest is the oldest
+    order: u64,
+}
+
+impl Bullet {
+    pub fn order(&self) -> u64 {
+        self.order
+    }
+}
+
+// Seconds until the player can fire again
+#[derive(Default)]
+struct FireCooldown(f32);
+
+// How many bullets have been fired, gives each bullet its order
+#[derive(Default)]
+struct BulletsFired(u64);
+
+#[allow(clippy::too_many_arguments)]
+fn fire_bullets(
+    mut commands: Commands,
+    player_input: Res<PlayerInput>,
+    player_query: Query<(&Player, &Transform, &Dimensions)>,
+    bullet_query: Query<&Bullet>,
+    mut fire_cooldown: ResMut<FireCooldown>,
+    mut bullets_fired: ResMut<BulletsFired>,
+    clock: Res<GameClock>,
+    config: Res<GameConfig>,
+) {
+    fire_cooldown.0 = (fire_cooldown.0 - clock.delta_seconds()).max(0.0);
+
+    if !player_input.fire || fire_cooldown.0 > 0.0 {
+        return;
+    }
+
+    if bullet_query.iter().count() >= config.max_bullets {
+        return;
+    }
+
+    if let Ok((player, transform, dimensions)) = player_query.single() {
+        // Start at the edge of the player so the bullet doesn't come out of their middle
+        let muzzle = player.facing * dimensions.0.max_element() / 2.0;
+        let position = transform.translation + muzzle.extend(0.0);
+        bullets_fired.0 += 1;
+
+        commands
+            .spawn()
+            .insert(Transform::from_translation(position))
+            .insert(Dimensions(Vec2::splat(config.bullet_radius * 2.0)))
+            .insert(Bullet {
+                velocity: player.facing * config.bullet_velocity,
+                lifetime: config.bullet_lifetime,
+                order: bullets_fired.0,
+            })
+            .insert(Collider::circle(config.bullet_radius));
+
+        fire_cooldown.0 = config.fire_cooldown;
+    }
+}
+
+fn move_bullets(
+    mut bullet_query: Query<(&mut Bullet, &mut Transform, &Dimensions)>,
+    clock: Res<GameClock>,
+    config: Res<GameConfig>,
+) {
+    for (mut bullet, mut transform, dimensions) in bullet_query.iter_mut() {
+        let delta_time = clock.delta_seconds();
+
+        transform.translation += (bullet.velocity * delta_time).extend(0.0);
+        bullet.lifetime -= delta_time;
+
+        // Bullets wrap the same way blocks do
+        wrap_around_screen(&mut transform.translation, dimensions.0, &config);
+    }
+}
+
+// A bullet only destroys one block, and a block is only destroyed once
+// no matter how many bullets hit it on the same tick.
+//
+// NOTE
+// The oldest bullet gets the first pick, and takes the oldest block it hit.
+// Entity ids depend on everything spawned before, windows and menus included,
+// so they can't decide which block is destroyed or replays would desync.
+fn destroy_shot_blocks(
+    mut collision_event: EventReader<CollisionEvent>,
+    mut block_destroyed_event: EventWriter<BlockDestroyedEvent>,
+    mut bullet_query: Query<&mut Bullet>,
+    block_query: Query<&Block>,
+) {
+    let mut hits: Vec<(u64, u64, Entity, Entity, BlockTier)> = collision_event
+        .iter()
+        .filter(|collision| collision.kind == CollisionKind::BulletBlock)
+        .filter_map(|hit| {
+            let bullet = bullet_query.get_mut(hit.a).ok()?;
+            let block = block_query.get(hit.b).ok()?;
+            Some((bullet.order, block.order(), hit.a, hit.b, block.tier))
+        })
+        .collect();
+    hits.sort_by_key(|(bullet_order, block_order, ..)| (*bullet_order, *block_order));
+
+    let mut destroyed_blocks = Vec::new();
+
+    for (_bullet_order, _block_order, bullet, block, tier) in hits {
+        if destroyed_blocks.contains(&block) {
+            continue;
+        }
+
+        if let Ok(mut bullet) = bullet_query.get_mut(bullet) {
+            // Ran out on this tick or already hit another block
+            if bullet.lifetime <= 0.0 {
+                continue;
+            }
+
+            bullet.lifetime = 0.0;
+        }
+
+        destroyed_blocks.push(block);
+        block_destroyed_event.send(BlockDestroyedEvent { block, tier });
+    }
+}
+
+fn despawn_spent_bullets(mut commands: Commands, bullet_query: Query<(Entity, &Bullet)>) {
+    for (entity, bullet) in bullet_query.iter() {
+        if bullet.lifetime <= 0.0 {
+            commands.entity(entity).despawn();
+        }
+    }
+}
+
+// Every run starts without any bullets in the air
+fn clear_bullets(
+    mut commands: Commands,
+    bullet_query: Query<Entity, With<Bullet>>,
+    mut fire_cooldown: ResMut<FireCooldown>,
+) {
+    for entity in bullet_query.iter() {
+        commands.entity(entity).despawn();
+    }
+
+    fire_cooldown.0 = 0.0;
+}
@@ -0,0 +1,549 @@
+// REPLAY CODE
+
+use bevy::app::AppExit;
+use bevy::prelude::*;
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::config::GameConfig;
+use crate::storage;
+
+use super::clock::GameClock;
+use super::difficulty::DifficultyPreset;
+use super::input::PlayerInput;
+use super::player::ControlScheme;
+use super::rng::GameRng;
+use super::state::GameState;
+use super::{GameSystem, RunSettings};
+
+// Bump this whenever Replay or RecordedRun changes shape,
+// or the same seed and inputs would play out differently
+const REPLAY_VERSION: u32 = 5;
+
+// Everything needed to play a session again tick for tick.
+//
+// The seed and the inputs decide everything that happens, so that is all
+// we keep. Each run's seed comes from the session's seed and how many runs
+// came before it, so a replay holds every run of the session and plays them
+// back in order.
+#[derive(Serialize, Deserialize)]
+pub struct Replay {
+    version: u32,
+    pub seed: u64,
+    pub config_hash: u64,
+    runs: Vec<RecordedRun>,
+}
+
+// One run, from the board being reset to the player dying
+#[derive(Default, Serialize, Deserialize)]
+struct RecordedRun {
+    control_scheme: ControlScheme,
+    preset: DifficultyPreset,
+    // The input bits for every tick of the run, stored as
+    // (bits, how many ticks in a row had them)
+    inputs: Vec<(u32, u32)>,
+    // The tick the player died on, counting from 0
+    death_tick: Option<u32>,
+}
+
+impl RecordedRun {
+    fn push(&mut self, bits: u32) {
+        match self.inputs.last_mut() {
+            Some((last_bits, count)) if *last_bits == bits => *count += 1,
+            _ => self.inputs.push((bits, 1)),
+        }
+    }
+
+    fn ticks(&self) -> u32 {
+        self.inputs.iter().map(|(_bits, count)| count).sum()
+    }
+}
+
+#[derive(Debug)]
+pub enum ReplayError {
+    Io(io::Error),
+    Parse(ron::Error),
+    Version(u32),
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ReplayError::Io(error) => write!(f, "Could not read the replay: {}", error),
+            ReplayError::Parse(error) => write!(f, "Could not parse the replay: {}", error),
+            ReplayError::Version(version) => write!(
+                f,
+                "The replay is version {}, this game plays version {}.",
+                version, REPLAY_VERSION
+            ),
+        }
+    }
+}
+
+impl Replay {
+    pub fn new(seed: u64, config_hash: u64) -> Self {
+        Replay {
+            version: REPLAY_VERSION,
+            seed,
+            config_hash,
+            runs: Vec::new(),
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Self, ReplayError> {
+        let contents = fs::read_to_string(path).map_err(ReplayError::Io)?;
+        let replay: Replay = ron::de::from_str(&contents).map_err(ReplayError::Parse)?;
+
+        if replay.version != REPLAY_VERSION {
+            return Err(ReplayError::Version(replay.version));
+        }
+
+        Ok(replay)
+    }
+}
+
+// Records every run into a replay file in the data directory.
+// Only added to windowed games that aren't playing a replay back.
+pub struct ReplayRecorderPlugin;
+
+impl Plugin for ReplayRecorderPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<Recorder>()
+            .add_system_set(
+                SystemSet::on_enter(GameState::Playing)
+                    .with_system(start_recorded_run.system().after(RunSettings)),
+            )
+            .add_system_set(
+                SystemSet::on_update(GameState::Playing)
+                    .with_system(record_input.system().after(GameSystem::Input)),
+            )
+            .add_system_set(
+                SystemSet::on_enter(GameState::GameOver).with_system(save_recorded_run.system()),
+            );
+    }
+}
+
+struct Recorder {
+    replay: Replay,
+    run: RecordedRun,
+    // Where the session is saved, None if there is nowhere to save it
+    directory: Option<PathBuf>,
+    // When the session started and its seed, the file is named after this
+    name: String,
+    // The file claimed the first time the session is saved
+    path: Option<PathBuf>,
+}
+
+impl FromWorld for Recorder {
+    fn from_world(world: &mut World) -> Self {
+        let seed = world
+            .get_resource::<GameRng>()
+            .expect("The RNG is added before the plugins.")
+            .seed();
+        let config_hash = world
+            .get_resource::<GameConfig>()
+            .expect("The game config is added before the plugins.")
+            .gameplay_hash();
+
+        let name = format!("{}_{}", Local::now().format("%Y-%m-%d_%H-%M-%S"), seed);
+
+        Recorder {
+            replay: Replay::new(seed, config_hash),
+            run: RecordedRun::default(),
+            directory: storage::data_dir().map(|directory| directory.join("replays")),
+            name,
+            path: None,
+        }
+    }
+}
+
+fn start_recorded_run(
+    mut recorder: ResMut<Recorder>,
+    control_scheme: Res<ControlScheme>,
+    preset: Res<DifficultyPreset>,
+) {
+    recorder.run = RecordedRun {
+        control_scheme: *control_scheme,
+        preset: *preset,
+        ..RecordedRun::default()
+    };
+}
+
+fn record_input(
+    mut recorder: ResMut<Recorder>,
+    player_input: Res<PlayerInput>,
+    clock: Res<GameClock>,
+) {
+    if clock.ticked() {
+        recorder.run.push(player_input.to_bits());
+    }
+}
+
+// The whole session is written out again after every run,
+// so quitting at any point keeps every finished run.
+fn save_recorded_run(mut recorder: ResMut<Recorder>) {
+    let mut run = std::mem::take(&mut recorder.run);
+    run.death_tick = run.ticks().checked_sub(1);
+    recorder.replay.runs.push(run);
+
+    let recorder = &mut *recorder;
+    if recorder.path.is_none() {
+        let directory = match &recorder.directory {
+            Some(directory) => directory,
+            None => return,
+        };
+
+        match claim_replay_path(directory, &recorder.name) {
+            Ok(path) => recorder.path = Some(path),
+            Err(error) => {
+                println!("Could not save the replay: {}", error);
+                return;
+            }
+        }
+    }
+
+    let path = recorder
+        .path
+        .as_ref()
+        .expect("The replay path was just claimed.");
+
+    let result = ron::ser::to_string(&recorder.replay)
+        .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))
+        .and_then(|contents| storage::write_atomically(path, &contents));
+
+    match result {
+        Ok(()) => println!("Saved the replay to {}.", path.display()),
+        Err(error) => println!("Could not save the replay: {}", error),
+    }
+}
+
+// NOTE
+// Two sessions can start in the same second with the same seed,
+// so the first free name is claimed by creating the file before it is written.
+fn claim_replay_path(directory: &Path, name: &str) -> io::Result<PathBuf> {
+    fs::create_dir_all(directory)?;
+
+    let mut copy = 1;
+    loop {
+        let file_name = match copy {
+            1 => format!("{}.ron", name),
+            _ => format!("{}_{}.ron", name, copy),
+        };
+        let path = directory.join(file_name);
+
+        match fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&path)
+        {
+            Ok(_) => return Ok(path),
+            Err(error) if error.kind() == io::ErrorKind::AlreadyExists => copy += 1,
+            Err(error) => return Err(error),
+        }
+    }
+}
+
+// Feeds the player input from a replay instead of the keyboard,
+// and checks every run ends on the same tick it did when recorded.
+//
+// The app has to use the replay's seed and skip the title screen.
+pub struct ReplayPlaybackPlugin;
+
+impl Plugin for ReplayPlaybackPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_system_set(
+            SystemSet::on_enter(GameState::Playing).with_system(start_played_back_run.system()),
+        )
+        .add_system_set(
+            SystemSet::on_update(GameState::Playing)
+                .with_system(read_replay_input.system().label(GameSystem::Input)),
+        )
+        .add_system_set(
+            SystemSet::on_enter(GameState::GameOver).with_system(finish_played_back_run.system()),
+        );
+    }
+}
+
+pub struct Playback {
+    replay: Replay,
+    run_index: usize,
+    // How many ticks of the current run have been played
+    tick: u32,
+    // Where we are in the current run's inputs
+    input_index: usize,
+    ticks_into_input: u32,
+    desync_reported: bool,
+    // Quit once every run has been played, for headless checks
+    exit_when_done: bool,
+}
+
+impl Playback {
+    pub fn new(replay: Replay, exit_when_done: bool) -> Self {
+        Playback {
+            replay,
+            run_index: 0,
+            tick: 0,
+            input_index: 0,
+            ticks_into_input: 0,
+            desync_reported: false,
+            exit_when_done,
+        }
+    }
+
+    // The control scheme and preset the current run was recorded with,
+    // None once every run has been played
+    pub fn run_settings(&self) -> Option<(ControlScheme, DifficultyPreset)> {
+        self.replay
+            .runs
+            .get(self.run_index)
+            .map(|run| (run.control_scheme, run.preset))
+    }
+
+    // The input for the next tick, None once the recorded run is over
+    fn next_input(&mut self) -> Option<u32> {
+        let (bits, count) = *self
+            .replay
+            .runs
+            .get(self.run_index)?
+            .inputs
+            .get(self.input_index)?;
+
+        self.ticks_into_input += 1;
+        if self.ticks_into_input == count {
+            self.input_index += 1;
+            self.ticks_into_input = 0;
+        }
+
+        Some(bits)
+    }
+}
+
+// The run's settings are set from the recording along with
+// everyone else's, see apply_run_settings
+fn start_played_back_run(mut playback: ResMut<Playback>) {
+    playback.tick = 0;
+    playback.input_index = 0;
+    playback.ticks_into_input = 0;
+    playback.desync_reported = false;
+}
+
+fn read_replay_input(
+    mut playback: ResMut<Playback>,
+    mut player_input: ResMut<PlayerInput>,
+    clock: Res<GameClock>,
+) {
+    if !clock.ticked() {
+        *player_input = PlayerInput::default();
+        return;
+    }
+
+    *player_input = match playback.next_input() {
+        Some(bits) => PlayerInput::from_bits(bits),
+        None => {
+            if !playback.desync_reported && playback.run_index < playback.replay.runs.len() {
+                println!(
+                    "Replay desynced, run {} is still going after its recorded death.",
+                    playback.run_index + 1
+                );
+                playback.desync_reported = true;
+            }
+
+            PlayerInput::default()
+        }
+    };
+
+    playback.tick += 1;
+}
+
+fn finish_played_back_run(
+    mut playback: ResMut<Playback>,
+    mut state: ResMut<State<GameState>>,
+    mut exit: EventWriter<AppExit>,
+) {
+    let run_number = playback.run_index + 1;
+    let death_tick = playback.tick.checked_sub(1);
+
+    if let Some(run) = playback.replay.runs.get(playback.run_index) {
+        if death_tick == run.death_tick {
+            println!("Run {} matches the replay.", run_number);
+        } else {
+            println!(
+                "Replay desynced, run {} ended on tick {:?} but was recorded ending on tick {:?}.",
+                run_number, death_tick, run.death_tick
+            );
+        }
+    }
+
+    playback.run_index += 1;
+
+    if playback.run_index < playback.replay.runs.len() {
+        // Can only fail if another transition is queued this frame
+        let _ = state.set(GameState::Playing);
+    } else {
+        println!("Finished playing the replay.");
+
+        if playback.exit_when_done {
+            exit.send(AppExit);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::app::{Events, ManualEventReader};
+    use bevy::ecs::system::Local;
+
+    use crate::config::DifficultyCurve;
+    use crate::logic::score::{Score, SurvivalTime};
+    use crate::testing::{self, TemporaryDirectory};
+
+    const SEED: u64 = 7;
+    const RUNS: usize = 3;
+
+    // How each run ended, as (ticks survived, score)
+    #[derive(Default)]
+    struct RunResults(Vec<(u32, i32)>);
+
+    // Blocks come in fast and one hit ends a run, so a few runs are over quickly
+    fn config() -> GameConfig {
+        GameConfig {
+            lives: 1,
+            difficulty: DifficultyCurve::constant(0.2),
+            ..GameConfig::default()
+        }
+    }
+
+    fn game_app() -> AppBuilder {
+        let mut builder = testing::game_app(config(), SEED);
+        builder.init_resource::<RunResults>().add_system_set(
+            SystemSet::on_enter(GameState::GameOver).with_system(keep_run_result.system()),
+        );
+        builder
+    }
+
+    fn keep_run_result(
+        survival_time: Res<SurvivalTime>,
+        score: Res<Score>,
+        mut run_results: ResMut<RunResults>,
+    ) {
+        run_results.0.push((survival_time.ticks, score.value));
+    }
+
+    // Moves, fires, teleports and pushes the stick around
+    fn scripted_input(mut tick: Local<u32>, mut player_input: ResMut<PlayerInput>) {
+        *tick += 1;
+        let tick = *tick;
+
+        *player_input = PlayerInput {
+            up: tick / 50 % 4 == 0,
+            down: tick / 50 % 4 == 2,
+            left: tick / 70 % 3 == 1,
+            right: tick / 70 % 3 == 2,
+            teleport: tick % 97 == 0,
+            fire: tick / 30 % 2 == 0,
+            stick: if tick / 200 % 2 == 1 {
+                (64, -127)
+            } else {
+                (0, 0)
+            },
+        };
+    }
+
+    // Nobody is there to press reset
+    fn restart_game(mut state: ResMut<State<GameState>>) {
+        let _ = state.set(GameState::Playing);
+    }
+
+    fn run_results(app: &App) -> &[(u32, i32)] {
+        &app.world.get_resource::<RunResults>().unwrap().0
+    }
+
+    fn record() -> (Replay, Vec<(u32, i32)>) {
+        let mut builder = game_app();
+        builder
+            .add_plugin(ReplayRecorderPlugin)
+            .add_system_set(
+                SystemSet::on_update(GameState::Playing)
+                    .with_system(scripted_input.system().label(GameSystem::Input)),
+            )
+            .add_system_set(
+                SystemSet::on_update(GameState::GameOver).with_system(restart_game.system()),
+            );
+        let mut app = builder.app;
+        // Keep the test's replay out of the real replays folder
+        app.world.get_resource_mut::<Recorder>().unwrap().directory = None;
+
+        for _ in 0..10_000 {
+            if run_results(&app).len() == RUNS {
+                break;
+            }
+            app.update();
+        }
+
+        let run_results = run_results(&app).to_vec();
+        assert_eq!(run_results.len(), RUNS);
+
+        let mut recorder = app.world.get_resource_mut::<Recorder>().unwrap();
+        let replay = std::mem::replace(&mut recorder.replay, Replay::new(0, 0));
+        (replay, run_results)
+    }
+
+    fn play_back(replay: Replay) -> Vec<(u32, i32)> {
+        let mut builder = game_app();
+        builder
+            .insert_resource(Playback::new(replay, true))
+            .add_plugin(ReplayPlaybackPlugin);
+        let mut app = builder.app;
+
+        // The replay has to play out the same when the entities come out different
+        let shifted: Vec<Entity> = (0..5).map(|_| app.world.spawn().id()).collect();
+        for entity in &shifted[..2] {
+            app.world.despawn(*entity);
+        }
+
+        let mut exit_reader = ManualEventReader::<AppExit>::default();
+        for _ in 0..10_000 {
+            app.update();
+
+            let exit_events = app.world.get_resource::<Events<AppExit>>().unwrap();
+            if exit_reader.iter(exit_events).next().is_some() {
+                break;
+            }
+        }
+
+        run_results(&app).to_vec()
+    }
+
+    #[test]
+    fn replay_plays_every_run_the_same() {
+        let (replay, recorded) = record();
+
+        let death_ticks: Vec<Option<u32>> = replay.runs.iter().map(|run| run.death_tick).collect();
+        let survived: Vec<Option<u32>> = recorded
+            .iter()
+            .map(|(ticks, _score)| ticks.checked_sub(1))
+            .collect();
+        assert_eq!(death_ticks, survived);
+
+        // Different runs, so this checks more than one seed
+        assert!(recorded.windows(2).any(|pair| pair[0] != pair[1]));
+
+        assert_eq!(play_back(replay), recorded);
+    }
+
+    #[test]
+    fn sessions_started_together_get_their_own_files() {
+        let directory = TemporaryDirectory::new("replay_names");
+
+        let first = claim_replay_path(directory.path(), "2021-01-01_12-00-00_42").unwrap();
+        let second = claim_replay_path(directory.path(), "2021-01-01_12-00-00_42").unwrap();
+
+        assert_eq!(first, directory.join("2021-01-01_12-00-00_42.ron"));
+        assert_eq!(second, directory.join("2021-01-01_12-00-00_42_2.ron"));
+    }
+}
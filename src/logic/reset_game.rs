@@ -1,7 +1,13 @@
-use super::blocks::SpawnBlockEvent;
-use crate::graphics::score::Score;
+use super::actions::{Action, InputActions};
+use super::blocks::{spawn_block, BlockPool, BlockSpawnTimer, BlockSpeed, BlockTier, SpawnWarning};
+use super::difficulty::DifficultyPreset;
+use super::rng::GameRng;
+use super::state::GameState;
+use super::RunSettings;
+use crate::config::GameConfig;
 use crate::logic::blocks::Block;
-use crate::logic::player::Player;
+use crate::logic::player::{Invulnerable, Lives, Player};
+use crate::logic::respawn::Respawning;
 use crate::logic::spawning::SpawnInfo;
 use bevy::prelude::*;
 
@@ -9,73 +15,89 @@ pub struct ResetGamePlugin;
 
 impl Plugin for ResetGamePlugin {
     fn build(&self, app: &mut AppBuilder) {
-        app.add_event::<ResetGameEvent>()
-            .add_system(send_reset_game_event.system())
-            .add_system(reset_game.system())
-            .add_system(reset_player.system());
+        app.add_system_set(
+            SystemSet::on_update(GameState::GameOver).with_system(restart_game.system()),
+        )
+        // Every run starts from a clean board, whether we came
+        // from the title screen or from a game over.
+        .add_system_set(
+            SystemSet::on_enter(GameState::Playing)
+                .with_system(reset_game.system().after(RunSettings))
+                .with_system(reset_player.system().after(RunSettings)),
+        );
     }
 }
 
-pub struct ResetGameEvent;
-
-fn send_reset_game_event(
-    keyboard_input: Res<Input<KeyCode>>,
-    mut reset_game_event: EventWriter<ResetGameEvent>,
-) {
-    if keyboard_input.pressed(KeyCode::R) {
-        reset_game_event.send(ResetGameEvent)
+fn restart_game(mut input_actions: ResMut<InputActions>, mut state: ResMut<State<GameState>>) {
+    // See the NOTE in the actions module about consuming presses
+    if input_actions.consume(Action::Reset) {
+        let _ = state.set(GameState::Playing);
     }
 }
 
 fn reset_player(
     mut commands: Commands,
-    asset_server: Res<AssetServer>,
-    mut materials: ResMut<Assets<ColorMaterial>>,
-    mut player_query: Query<&mut Transform, With<Player>>,
-    mut reset_game_event: EventReader<ResetGameEvent>,
+    mut player_query: Query<(Entity, &mut Player, &mut Transform)>,
+    mut lives: ResMut<Lives>,
+    preset: Res<DifficultyPreset>,
+    config: Res<GameConfig>,
 ) {
-    for _event in reset_game_event.iter() {
-        // Reset player position to 0,0
-        if let Ok(mut transform) = player_query.single_mut() {
-            transform.translation.x = 0.0;
-            transform.translation.y = 0.0;
-        } else {
-            // If the player is dead, spawn a new one
-            super::player::spawn_player(&mut commands, &asset_server, &mut materials)
-        }
+    *lives = Lives(config.lives);
+
+    // The respawn module finds the player somewhere to start once the new blocks are in
+    if let Ok((entity, mut player, mut transform)) = player_query.single_mut() {
+        transform.rotation = Quat::IDENTITY;
+        // The preset may have changed since the last run
+        *player = Player::new(&config, *preset);
+        commands
+            .entity(entity)
+            .remove::<Invulnerable>()
+            .insert(Respawning);
+    } else {
+        // If the player is dead, spawn a new one
+        super::player::spawn_player(&mut commands, &config, *preset);
     }
 }
 
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
 fn reset_game(
-    mut reset_game_event: EventReader<ResetGameEvent>,
-    block_query: Query<Entity, With<Block>>,
+    block_query: Query<Entity, Or<(With<Block>, With<SpawnWarning>)>>,
     mut commands: Commands,
-    mut score_query: Query<&mut Score>,
+    mut spawn_timer: ResMut<BlockSpawnTimer>,
+    mut block_pool: ResMut<BlockPool>,
+    mut block_speed: ResMut<BlockSpeed>,
     mut spawn_positions_query: Query<(Entity, &mut SpawnInfo)>,
-    mut spawn_event: EventWriter<SpawnBlockEvent>,
+    mut rng: ResMut<GameRng>,
+    preset: Res<DifficultyPreset>,
+    config: Res<GameConfig>,
 ) {
-    for _event in reset_game_event.iter() {
-        // Despawn all of the blocks
-        for entity in block_query.iter() {
-            commands.entity(entity).despawn();
-        }
+    // Put every block back in the pool, the starting blocks come straight back out of it
+    for entity in block_query.iter() {
+        block_pool.recycle(&mut commands, entity);
+    }
 
-        // Reset the score
-        if let Ok(mut score) = score_query.single_mut() {
-            // NOTE It adds one automatically in score system
-            score.active = true;
-            score.value = -1;
-        }
+    spawn_timer.0.reset();
+    *block_speed = BlockSpeed::default();
 
-        // Reset the spawn positions
-        let mut counter = 0;
-        for (entity, mut spawn_positions) in spawn_positions_query.iter_mut() {
-            spawn_positions.spawned = false;
+    // Reset the spawn positions and spawn the starting blocks
+    let starting_blocks = preset.starting_blocks(&config);
+    let mut counter = 0;
+    for (slot, mut spawn_position) in spawn_positions_query.iter_mut() {
+        spawn_position.spawned = false;
+        // The last run turned some of them around, this run only depends on its own seed
+        spawn_position.spawn_direction = spawn_position.direction.random_direction(&mut *rng);
 
-            if counter < 6 {
-                spawn_event.send(SpawnBlockEvent(entity));
-                counter += 1;
-            }
+        if counter < starting_blocks {
+            spawn_block(
+                &mut commands,
+                &mut block_pool,
+                slot,
+                &mut spawn_position,
+                BlockTier::Large,
+                config.starting_difficulty().spawn_warning,
+                &config,
+            );
+            counter += 1;
         }
     }
 }
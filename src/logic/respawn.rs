@@ -0,0 +1,269 @@
+// RESPAWN CODE
+
+use bevy::prelude::*;
+
+use crate::config::GameConfig;
+use crate::Dimensions;
+
+use super::blocks::{Block, BlockSpeed, SpawnWarning};
+use super::clock::TIMESTEP;
+use super::collision::Collider;
+use super::difficulty::Difficulty;
+use super::player::Player;
+use super::state::GameState;
+use super::{wrap_around_screen, GameSystem};
+
+// Candidates along each side of the grid, odd so the middle of the screen is one
+const RESPAWN_GRID: usize = 5;
+// How much of the screen the grid covers, away from the edges blocks come in from
+const RESPAWN_AREA: f32 = 0.75;
+
+// Places a player that has just come into play wherever the blocks
+// would take the longest to reach them.
+pub struct RespawnPlugin;
+
+impl Plugin for RespawnPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_system_set(
+            SystemSet::on_update(GameState::Playing).with_system(
+                place_respawning_player
+                    .system()
+                    .after(GameSystem::Difficulty)
+                    .before(GameSystem::Movement),
+            ),
+        );
+    }
+}
+
+// A player that still needs a safe place to stand.
+// They are hidden until they get one.
+pub struct Respawning;
+
+// A block as the solver sees it, moving in a straight line and wrapping
+// around the screen just like move_blocks does
+#[derive(Debug, Clone, Copy)]
+pub struct ProjectedBlock {
+    pub position: Vec2,
+    // in pixels per second, with the difficulty already applied
+    pub velocity: Vec2,
+    pub size: Vec2,
+    pub collider: Collider,
+    // Seconds before the block comes into play, for spawn warnings
+    pub delay: f32,
+}
+
+// NOTE
+// This runs before anything moves on the tick after the player is spawned,
+// so it sees the board as the player will, blocks spawned alongside the player included.
+#[allow(clippy::type_complexity)]
+fn place_respawning_player(
+    mut commands: Commands,
+    mut player_query: Query<(Entity, &mut Transform, &Collider), (With<Player>, With<Respawning>)>,
+    block_query: Query<(&Block, &Transform, &Dimensions, &Collider), Without<Player>>,
+    warning_query: Query<(&SpawnWarning, &Transform, &Dimensions), Without<Player>>,
+    difficulty: Res<Difficulty>,
+    block_speed: Res<BlockSpeed>,
+    config: Res<GameConfig>,
+) {
+    let (entity, mut transform, player_collider) = match player_query.single_mut() {
+        Ok(player) => player,
+        Err(_) => return,
+    };
+
+    let speed = difficulty.block_speed * block_speed.0;
+    let block_collider = |dimensions: &Dimensions| {
+        Collider::aabb(dimensions.0).with_inset(config.block_hitbox_inset)
+    };
+
+    let mut blocks: Vec<ProjectedBlock> = block_query
+        .iter()
+        .map(|(block, transform, dimensions, collider)| ProjectedBlock {
+            position: transform.translation.truncate(),
+            velocity: block.velocity() * speed,
+            size: dimensions.0,
+            collider: *collider,
+            delay: 0.0,
+        })
+        .collect();
+
+    blocks.extend(
+        warning_query
+            .iter()
+            .map(|(warning, transform, dimensions)| ProjectedBlock {
+                position: transform.translation.truncate(),
+                velocity: warning.block.velocity() * speed,
+                size: dimensions.0,
+                collider: block_collider(dimensions),
+                delay: warning.timer.duration().as_secs_f32() - warning.timer.elapsed_secs(),
+            }),
+    );
+
+    let position = safest_position(
+        &respawn_candidates(&config),
+        player_collider,
+        &blocks,
+        config.respawn_horizon,
+        &config,
+    );
+
+    transform.translation.x = position.x;
+    transform.translation.y = position.y;
+    commands.entity(entity).remove::<Respawning>();
+}
+
+// A grid of places to respawn, closest to the middle of the screen first
+pub fn respawn_candidates(config: &GameConfig) -> Vec<Vec2> {
+    let half_extent = Vec2::new(config.half_width(), config.half_height()) * RESPAWN_AREA;
+    let last = (RESPAWN_GRID - 1) as f32;
+
+    let mut candidates: Vec<Vec2> = (0..RESPAWN_GRID)
+        .flat_map(|row| (0..RESPAWN_GRID).map(move |column| (row, column)))
+        .map(|(row, column)| {
+            let fraction = Vec2::new(column as f32, row as f32) / last;
+            (fraction * 2.0 - Vec2::ONE) * half_extent
+        })
+        .collect();
+
+    // The sort is stable, so candidates the same distance out keep the grid order
+    candidates.sort_by(|a, b| a.length_squared().partial_cmp(&b.length_squared()).unwrap());
+    candidates
+}
+
+// The candidate the blocks would take the longest to reach, checked one tick
+// at a time up to the horizon. Ties go to the earlier candidate.
+pub fn safest_position(
+    candidates: &[Vec2],
+    player: &Collider,
+    blocks: &[ProjectedBlock],
+    horizon: f32,
+    config: &GameConfig,
+) -> Vec2 {
+    let times = times_to_collision(candidates, player, blocks, horizon, config);
+
+    let mut best = 0;
+    for (index, time) in times.iter().enumerate() {
+        if *time > times[best] {
+            best = index;
+        }
+    }
+
+    candidates.get(best).copied().unwrap_or(Vec2::ZERO)
+}
+
+// How long until a block first touches a player standing still at each candidate.
+// Candidates no block reaches within the horizon get the horizon.
+pub fn times_to_collision(
+    candidates: &[Vec2],
+    player: &Collider,
+    blocks: &[ProjectedBlock],
+    horizon: f32,
+    config: &GameConfig,
+) -> Vec<f32> {
+    let ticks = (horizon / TIMESTEP).ceil() as u32;
+    let mut times = vec![horizon; candidates.len()];
+
+    for block in blocks {
+        let mut position = block.position.extend(0.0);
+
+        for tick in 0..=ticks {
+            let time = tick as f32 * TIMESTEP;
+
+            if time >= block.delay {
+                for (candidate, candidate_time) in candidates.iter().zip(times.iter_mut()) {
+                    if time < *candidate_time
+                        && player.overlaps(*candidate, &block.collider, position.truncate())
+                    {
+                        *candidate_time = time;
+                    }
+                }
+
+                position += (block.velocity * TIMESTEP).extend(0.0);
+                wrap_around_screen(&mut position, block.size, config);
+            }
+        }
+    }
+
+    times
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn block(position: Vec2, velocity: Vec2) -> ProjectedBlock {
+        let size = Vec2::new(60.0, 60.0);
+
+        ProjectedBlock {
+            position,
+            velocity,
+            size,
+            collider: Collider::aabb(size),
+            delay: 0.0,
+        }
+    }
+
+    #[test]
+    fn stays_in_the_middle_when_nothing_is_coming() {
+        let config = GameConfig::default();
+        let player = Collider::aabb(Vec2::new(40.0, 40.0));
+        let far_away = block(Vec2::new(0.0, config.half_height()), Vec2::new(100.0, 0.0));
+
+        let position = safest_position(
+            &respawn_candidates(&config),
+            &player,
+            &[far_away],
+            2.0,
+            &config,
+        );
+
+        assert_eq!(position, Vec2::ZERO);
+    }
+
+    #[test]
+    fn moves_out_of_the_way_of_a_block_passing_through_the_middle() {
+        let config = GameConfig::default();
+        let player = Collider::aabb(Vec2::new(40.0, 40.0));
+        let passing = block(Vec2::new(-100.0, 0.0), Vec2::new(200.0, 0.0));
+
+        let position = safest_position(
+            &respawn_candidates(&config),
+            &player,
+            &[passing],
+            2.0,
+            &config,
+        );
+
+        assert_ne!(position, Vec2::ZERO);
+        // Anywhere off the block's row is safe for the whole horizon
+        assert!(position.y.abs() > 50.0);
+    }
+
+    #[test]
+    fn sees_blocks_coming_around_the_edge_of_the_screen() {
+        let config = GameConfig::default();
+        let player = Collider::aabb(Vec2::new(40.0, 40.0));
+        // About to go off the right edge and come back in on the left
+        let wrapping = block(
+            Vec2::new(config.half_width() + 50.0, 0.0),
+            Vec2::new(400.0, 0.0),
+        );
+
+        let times = times_to_collision(&[Vec2::ZERO], &player, &[wrapping], 5.0, &config);
+
+        assert!(times[0] < 5.0);
+    }
+
+    #[test]
+    fn warned_blocks_can_only_hit_once_they_come_in() {
+        let config = GameConfig::default();
+        let player = Collider::aabb(Vec2::new(40.0, 40.0));
+        let warned = ProjectedBlock {
+            delay: 1.0,
+            ..block(Vec2::ZERO, Vec2::ZERO)
+        };
+
+        let times = times_to_collision(&[Vec2::ZERO], &player, &[warned], 2.0, &config);
+
+        assert!((times[0] - 1.0).abs() <= TIMESTEP);
+    }
+}
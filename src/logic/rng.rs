@@ -0,0 +1,164 @@
+// RANDOMNESS CODE
+
+use bevy::prelude::*;
+use rand::rngs::StdRng;
+use rand::{thread_rng, Rng, RngCore, SeedableRng};
+
+use super::state::GameState;
+use super::RunSettings;
+
+pub struct RngPlugin;
+
+impl Plugin for RngPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<GameRng>().add_system_set(
+            SystemSet::on_enter(GameState::Playing)
+                .with_system(start_run.system().label(RunSettings)),
+        );
+    }
+}
+
+// The one source of randomness for the whole game.
+//
+// Every random decision has to draw from this so that
+// the same seed plays out the same spawn grid, directions
+// and spawn order.
+//
+// Each run starts over from its own seed, the session's seed plus
+// how many runs came before it. Starting the game with a run's seed
+// plays that run again as the first one.
+//
+// NOTE
+// Systems that draw from this need to run in a fixed order,
+// otherwise the order they take numbers in can change between runs.
+pub struct GameRng {
+    seed: u64,
+    run_seed: u64,
+    runs: u64,
+    rng: StdRng,
+}
+
+impl GameRng {
+    pub fn new(seed: u64) -> Self {
+        GameRng {
+            seed,
+            run_seed: seed,
+            runs: 0,
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+
+    // The seed the session started with
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    // The seed the current run started from
+    pub fn run_seed(&self) -> u64 {
+        self.run_seed
+    }
+
+    fn start_run(&mut self) {
+        self.run_seed = self.seed.wrapping_add(self.runs);
+        self.runs += 1;
+        self.rng = StdRng::seed_from_u64(self.run_seed);
+    }
+}
+
+// Without a seed from the command line, pick one at random
+impl Default for GameRng {
+    fn default() -> Self {
+        GameRng::new(thread_rng().gen())
+    }
+}
+
+// Lets GameRng be passed anywhere rand expects an Rng
+impl RngCore for GameRng {
+    fn next_u32(&mut self) -> u32 {
+        self.rng.next_u32()
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.rng.next_u64()
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        self.rng.fill_bytes(dest)
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        self.rng.try_fill_bytes(dest)
+    }
+}
+
+// Runs before anything else draws from the RNG as a run starts
+fn start_run(mut rng: ResMut<GameRng>) {
+    rng.start_run();
+    println!("Seed is {}.", rng.run_seed());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::GameConfig;
+    use crate::logic::blocks::Block;
+    use crate::logic::input::DeviceInputPlugin;
+    use crate::testing;
+
+    // Every block that comes into play over the first 20 seconds of an idle run,
+    // in the order they come in
+    fn spawned_blocks(seed: u64) -> Vec<String> {
+        let mut builder = testing::game_app(GameConfig::default(), seed);
+        builder.add_plugin(DeviceInputPlugin);
+        let mut app = builder.app;
+
+        let mut spawned = Vec::new();
+        for _ in 0..1200 {
+            app.update();
+
+            let mut blocks = app
+                .world
+                .query_filtered::<(&Block, &Transform), Added<Block>>();
+            let mut added: Vec<(u64, String)> = blocks
+                .iter(&app.world)
+                .map(|(block, transform)| {
+                    let block_info = format!(
+                        "{:?} {:?} {:?}",
+                        block.tier,
+                        transform.translation.truncate(),
+                        block.velocity()
+                    );
+                    (block.order(), block_info)
+                })
+                .collect();
+            added.sort_by_key(|(order, _block_info)| *order);
+            spawned.extend(added.into_iter().map(|(_order, block_info)| block_info));
+        }
+
+        spawned
+    }
+
+    #[test]
+    fn seed_decides_the_spawn_order() {
+        let spawned = spawned_blocks(42);
+        assert!(spawned.len() > 6);
+        assert_eq!(spawned, spawned_blocks(42));
+        assert_ne!(spawned, spawned_blocks(43));
+    }
+
+    #[test]
+    fn a_runs_seed_plays_it_again_as_the_first_run() {
+        let mut session = GameRng::new(42);
+        session.next_u64();
+        session.start_run();
+        assert_eq!(session.run_seed(), 42);
+        session.start_run();
+        assert_eq!(session.run_seed(), 43);
+        assert_eq!(session.seed(), 42);
+
+        let mut again = GameRng::new(43);
+        again.start_run();
+        assert_eq!(again.run_seed(), 43);
+        assert_eq!(session.next_u64(), again.next_u64());
+    }
+}
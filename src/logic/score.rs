@@ -0,0 +1,371 @@
+// SCORE CODE
+
+use bevy::prelude::*;
+
+use crate::config::GameConfig;
+
+use super::blocks::{Block, BlockDestroyedEvent};
+use super::clock::{GameClock, TIMESTEP};
+use super::collision::Collider;
+use super::player::{Invulnerable, Player};
+use super::spatial_hash::SpatialHash;
+use super::state::GameState;
+use super::GameSystem;
+
+pub struct ScorePlugin;
+
+impl Plugin for ScorePlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_event::<PickupCollectedEvent>()
+            .init_resource::<Score>()
+            .init_resource::<SurvivalTime>()
+            .init_resource::<NearMisses>()
+            // The score only goes up while the game is being played
+            .add_system_set(
+                SystemSet::on_update(GameState::Playing)
+                    // The difficulty can depend on the score, see the difficulty module
+                    .with_system(score_update_system.system().before(GameSystem::Difficulty))
+                    .with_system(
+                        award_block_points
+                            .system()
+                            .after(GameSystem::CollisionResponse),
+                    )
+                    .with_system(
+                        award_pickup_points
+                            .system()
+                            .after(GameSystem::CollisionResponse),
+                    )
+                    .with_system(
+                        award_near_misses
+                            .system()
+                            .after(GameSystem::CollisionResponse),
+                    ),
+            )
+            .add_system_set(
+                SystemSet::on_enter(GameState::Playing).with_system(reset_score.system()),
+            );
+    }
+}
+
+// Sent once for every pickup the player collects, with the points it is worth.
+// Nothing hands out pickups yet, this is where they will add to the score.
+pub struct PickupCollectedEvent {
+    pub points: i32,
+}
+
+// Points from one source of bonuses, and how many times they were earned
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Bonus {
+    pub count: u32,
+    pub points: i32,
+}
+
+impl Bonus {
+    fn add(&mut self, points: i32) {
+        self.count += 1;
+        self.points += points;
+    }
+}
+
+// Points for surviving plus every bonus, each kept apart
+// so the game over screen can show where they came from
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Score {
+    // Everything added up, this is what the HUD, high scores and difficulty curve use
+    pub value: i32,
+    pub survival: i32,
+    pub near_misses: Bonus,
+    pub destroyed_blocks: Bonus,
+    pub pickups: Bonus,
+}
+
+impl Score {
+    fn recompute_total(&mut self) {
+        self.value = self.survival
+            + self.near_misses.points
+            + self.destroyed_blocks.points
+            + self.pickups.points;
+    }
+
+    // Where the points came from, one line each
+    pub fn breakdown(&self, survival_time: &SurvivalTime) -> String {
+        format!(
+            "Survived {:.2} seconds: {}\nNear misses: {} for {}\nBlocks destroyed: {} for {}\nPickups: {} for {}\nTotal: {}",
+            survival_time.seconds(),
+            self.survival,
+            self.near_misses.count,
+            self.near_misses.points,
+            self.destroyed_blocks.count,
+            self.destroyed_blocks.points,
+            self.pickups.count,
+            self.pickups.points,
+            self.value
+        )
+    }
+}
+
+// How long the player has lived this run, kept out of the score since it changes every tick.
+// Counted in ticks so the time survived is exact, however long the run goes.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct SurvivalTime {
+    pub ticks: u32,
+}
+
+impl SurvivalTime {
+    pub fn seconds(&self) -> f32 {
+        self.ticks as f32 * TIMESTEP
+    }
+
+    // Survival points are only whole once the time for them has fully passed
+    fn points(&self, config: &GameConfig) -> i32 {
+        (self.ticks as f64 * TIMESTEP as f64 * config.survival_points as f64).floor() as i32
+    }
+}
+
+// Blocks that are close to the player right now.
+// A block that moves off again without ever touching the player is a near miss.
+#[derive(Default)]
+struct NearMisses {
+    // Close calls only count for the player they were close to
+    player: Option<Entity>,
+    // (block, its order, whether it touched the player), the order tells a block
+    // apart from the same entity coming back out of the pool
+    close: Vec<(Entity, u64, bool)>,
+}
+
+impl NearMisses {
+    // Takes (block, its order, whether it is near the player, whether it touches them)
+    // for every block in play that is near now or was last tick, and gives back
+    // how many blocks got away since last tick.
+    // Blocks that were shot or went back to the pool aren't in play, so they didn't get away.
+    fn update(&mut self, blocks: &[(Entity, u64, bool, bool)], invulnerable: bool) -> u32 {
+        let mut close = Vec::new();
+        let mut missed = 0;
+
+        for &(entity, order, near, touching) in blocks {
+            let before = self
+                .close
+                .iter()
+                .find(|(other, other_order, _)| *other == entity && *other_order == order);
+            // Blinking through blocks doesn't earn near misses either
+            let touched = touching || invulnerable || matches!(before, Some((_, _, true)));
+
+            if near {
+                close.push((entity, order, touched));
+            } else if before.is_some() && !touched {
+                missed += 1;
+            }
+        }
+
+        self.close = close;
+        missed
+    }
+}
+
+// Every run starts from zero
+fn reset_score(
+    mut score: ResMut<Score>,
+    mut survival_time: ResMut<SurvivalTime>,
+    mut near_misses: ResMut<NearMisses>,
+) {
+    *score = Score::default();
+    *survival_time = SurvivalTime::default();
+    *near_misses = NearMisses::default();
+}
+
+fn score_update_system(
+    mut score: ResMut<Score>,
+    mut survival_time: ResMut<SurvivalTime>,
+    clock: Res<GameClock>,
+    config: Res<GameConfig>,
+) {
+    if !clock.ticked() {
+        return;
+    }
+
+    survival_time.ticks += 1;
+    let survival = survival_time.points(&config);
+
+    // Only touch the score when it changes so the HUD isn't redrawn every frame
+    if survival != score.survival {
+        score.survival = survival;
+        score.recompute_total();
+    }
+}
+
+fn award_block_points(
+    mut score: ResMut<Score>,
+    mut block_destroyed_event: EventReader<BlockDestroyedEvent>,
+    config: Res<GameConfig>,
+) {
+    for destroyed in block_destroyed_event.iter() {
+        score
+            .destroyed_blocks
+            .add(destroyed.tier.config(&config).points);
+        score.recompute_total();
+    }
+}
+
+fn award_pickup_points(
+    mut score: ResMut<Score>,
+    mut pickup_collected_event: EventReader<PickupCollectedEvent>,
+) {
+    for collected in pickup_collected_event.iter() {
+        score.pickups.add(collected.points);
+        score.recompute_total();
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn award_near_misses(
+    mut score: ResMut<Score>,
+    mut near_misses: ResMut<NearMisses>,
+    player_query: Query<(Entity, &Collider, &Transform, Option<&Invulnerable>), With<Player>>,
+    block_query: Query<(&Block, &Collider, &Transform)>,
+    spatial_hash: Res<SpatialHash>,
+    clock: Res<GameClock>,
+    config: Res<GameConfig>,
+) {
+    if !clock.ticked() {
+        return;
+    }
+
+    let (player, collider, transform, invulnerable) = match player_query.single() {
+        Ok(player) => player,
+        Err(_) => {
+            *near_misses = NearMisses::default();
+            return;
+        }
+    };
+
+    // A new player after losing a life starts with no close calls
+    if near_misses.player != Some(player) {
+        *near_misses = NearMisses {
+            player: Some(player),
+            close: Vec::new(),
+        };
+    }
+
+    let position = transform.translation.truncate();
+    let near_collider = collider.with_inset(collider.inset - config.near_miss_distance);
+
+    // Blocks near the player now, and the ones that were last tick in case they left
+    let mut entities = spatial_hash.nearby(player, config.near_miss_distance);
+    entities.extend(near_misses.close.iter().map(|(entity, _, _)| *entity));
+    entities.sort();
+    entities.dedup();
+
+    let blocks: Vec<(Entity, u64, bool, bool)> = entities
+        .into_iter()
+        .filter_map(|entity| {
+            let (block, block_collider, block_transform) = block_query.get(entity).ok()?;
+            let block_position = block_transform.translation.truncate();
+
+            Some((
+                entity,
+                block.order(),
+                near_collider.overlaps(position, block_collider, block_position),
+                collider.overlaps(position, block_collider, block_position),
+            ))
+        })
+        .collect();
+
+    for _ in 0..near_misses.update(&blocks, invulnerable.is_some()) {
+        score.near_misses.add(config.near_miss_points);
+        score.recompute_total();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn survival_points_count_every_tick() {
+        let config = GameConfig {
+            survival_points: 10.0,
+            ..GameConfig::default()
+        };
+        // A tenth of a second is one point at ten a second
+        assert_eq!(SurvivalTime { ticks: 5 }.points(&config), 0);
+
+        let survival_time = SurvivalTime { ticks: 6 };
+        assert_eq!(survival_time.points(&config), 1);
+        assert!((survival_time.seconds() - 0.1).abs() < 1e-6);
+    }
+
+    #[test]
+    fn bonuses_add_to_the_total_separately() {
+        let config = GameConfig::default();
+        let mut score = Score {
+            survival: SurvivalTime { ticks: 60 }.points(&config),
+            ..Score::default()
+        };
+        score.near_misses.add(2);
+        score.pickups.add(15);
+        score.recompute_total();
+
+        assert_eq!(score.survival, 1);
+        assert_eq!(
+            score.near_misses,
+            Bonus {
+                count: 1,
+                points: 2
+            }
+        );
+        assert_eq!(
+            score.pickups,
+            Bonus {
+                count: 1,
+                points: 15
+            }
+        );
+        assert_eq!(score.value, 18);
+    }
+
+    fn block(id: u32) -> Entity {
+        Entity::new(id)
+    }
+
+    #[test]
+    fn a_block_that_passes_close_and_leaves_is_a_near_miss() {
+        let mut near_misses = NearMisses::default();
+
+        assert_eq!(near_misses.update(&[(block(1), 7, true, false)], false), 0);
+        assert_eq!(near_misses.update(&[(block(1), 7, true, false)], false), 0);
+        assert_eq!(near_misses.update(&[(block(1), 7, false, false)], false), 1);
+        // It only counts once
+        assert_eq!(near_misses.update(&[(block(1), 7, false, false)], false), 0);
+    }
+
+    #[test]
+    fn a_block_that_touched_the_player_is_not_a_near_miss() {
+        let mut near_misses = NearMisses::default();
+
+        near_misses.update(&[(block(1), 7, true, false)], false);
+        near_misses.update(&[(block(1), 7, true, true)], false);
+        near_misses.update(&[(block(1), 7, true, false)], false);
+
+        assert_eq!(near_misses.update(&[(block(1), 7, false, false)], false), 0);
+    }
+
+    #[test]
+    fn a_block_reused_from_the_pool_is_not_a_near_miss() {
+        let mut near_misses = NearMisses::default();
+
+        near_misses.update(&[(block(1), 7, true, false)], false);
+
+        // The same entity came back out of the pool far away as a new block
+        assert_eq!(near_misses.update(&[(block(1), 8, false, false)], false), 0);
+    }
+
+    #[test]
+    fn a_block_passing_an_invulnerable_player_is_not_a_near_miss() {
+        let mut near_misses = NearMisses::default();
+
+        near_misses.update(&[(block(1), 7, true, false)], true);
+        near_misses.update(&[(block(1), 7, true, false)], false);
+
+        assert_eq!(near_misses.update(&[(block(1), 7, false, false)], false), 0);
+    }
+}
@@ -0,0 +1,314 @@
+// SPATIAL HASH CODE
+
+use bevy::prelude::*;
+use std::collections::HashMap;
+
+use crate::config::GameConfig;
+
+use super::blocks::Block;
+use super::clock::GameClock;
+use super::collision::{Collider, ColliderOrder};
+use super::projectiles::Bullet;
+use super::state::GameState;
+use super::GameSystem;
+
+pub struct SpatialHashPlugin;
+
+impl Plugin for SpatialHashPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<SpatialHash>().add_system_set(
+            SystemSet::on_update(GameState::Playing).with_system(
+                rebuild_spatial_hash
+                    .system()
+                    .after(GameSystem::Teleport)
+                    .before(GameSystem::Collision),
+            ),
+        );
+    }
+}
+
+// A uniform grid over the play area that knows which colliders are in each cell,
+// so collision checks only look at what is close instead of everything.
+//
+// Entities wrap from one edge of the screen to the other, so the grid wraps too.
+// A cell on the right edge is next to the cell on the left edge of the same row.
+//
+// Rebuilt on every tick after everything has moved.
+pub struct SpatialHash {
+    cell_size: f32,
+    // The corner of the grid, everything left of or below it wraps around
+    origin: Vec2,
+    columns: i32,
+    rows: i32,
+    // Indexed by row * columns + column, each cell is sorted oldest first
+    cells: Vec<Vec<(ColliderOrder, Entity)>>,
+    // Where each entity is and half the size of its hitbox
+    bounds: HashMap<Entity, (Vec2, Vec2)>,
+}
+
+impl FromWorld for SpatialHash {
+    fn from_world(world: &mut World) -> Self {
+        let config = world
+            .get_resource::<GameConfig>()
+            .expect("The game config is added before the plugins.");
+
+        // Entities are allowed this far off screen before they wrap
+        let margin = config
+            .block_tiers()
+            .iter()
+            .map(|tier| tier.size.0.max(tier.size.1))
+            .fold(config.player_size.0.max(config.player_size.1), f32::max);
+
+        SpatialHash::new(
+            Vec2::new(config.window_width, config.window_height) + Vec2::splat(2.0 * margin),
+            margin * 2.0,
+        )
+    }
+}
+
+impl SpatialHash {
+    // A grid covering an area of the given size, centered on the origin
+    pub fn new(area: Vec2, cell_size: f32) -> Self {
+        let columns = (area.x / cell_size).ceil().max(1.0) as i32;
+        let rows = (area.y / cell_size).ceil().max(1.0) as i32;
+
+        SpatialHash {
+            cell_size,
+            origin: -Vec2::new(columns as f32, rows as f32) * cell_size / 2.0,
+            columns,
+            rows,
+            cells: vec![Vec::new(); (columns * rows) as usize],
+            bounds: HashMap::new(),
+        }
+    }
+
+    pub fn clear(&mut self) {
+        for cell in self.cells.iter_mut() {
+            cell.clear();
+        }
+
+        self.bounds.clear();
+    }
+
+    // Adds the entity to every cell its hitbox touches
+    pub fn insert(
+        &mut self,
+        entity: Entity,
+        order: ColliderOrder,
+        position: Vec2,
+        collider: &Collider,
+    ) {
+        let half_size = collider.half_extents();
+        self.bounds.insert(entity, (position, half_size));
+
+        for index in self.cell_indices(position, half_size) {
+            let cell = &mut self.cells[index];
+
+            if let Err(place) = cell.binary_search(&(order, entity)) {
+                cell.insert(place, (order, entity));
+            }
+        }
+    }
+
+    // Every other entity whose hitbox might be within `radius` pixels of this one's.
+    // This is only a rough check, the shapes still need testing against each other.
+    //
+    // Sorted oldest first so systems that act on the result stay deterministic,
+    // see ColliderOrder for why the entity can't be used.
+    pub fn nearby(&self, entity: Entity, radius: f32) -> Vec<Entity> {
+        let (position, half_size) = match self.bounds.get(&entity) {
+            Some(bounds) => *bounds,
+            None => return Vec::new(),
+        };
+
+        let mut found: Vec<(ColliderOrder, Entity)> = self
+            .cell_indices(position, half_size + Vec2::splat(radius))
+            .flat_map(|index| self.cells[index].iter().copied())
+            .filter(|(_order, other)| *other != entity)
+            .collect();
+
+        found.sort();
+        found.dedup();
+        found.into_iter().map(|(_order, other)| other).collect()
+    }
+
+    // The cells an area covers, wrapping around the edges of the grid
+    fn cell_indices(&self, position: Vec2, half_size: Vec2) -> impl Iterator<Item = usize> {
+        let min = self.cell_coordinates(position - half_size);
+        let max = self.cell_coordinates(position + half_size);
+
+        // An area bigger than the grid still only visits each cell once
+        let max_x = max.0.min(min.0 + self.columns - 1);
+        let max_y = max.1.min(min.1 + self.rows - 1);
+
+        let columns = self.columns;
+        let rows = self.rows;
+
+        (min.1..=max_y).flat_map(move |y| {
+            (min.0..=max_x)
+                .map(move |x| (y.rem_euclid(rows) * columns + x.rem_euclid(columns)) as usize)
+        })
+    }
+
+    // Cell coordinates before wrapping, so they can be outside the grid
+    fn cell_coordinates(&self, position: Vec2) -> (i32, i32) {
+        let cell = (position - self.origin) / self.cell_size;
+        (cell.x.floor() as i32, cell.y.floor() as i32)
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn rebuild_spatial_hash(
+    mut spatial_hash: ResMut<SpatialHash>,
+    collider_query: Query<(
+        Entity,
+        &Collider,
+        &Transform,
+        Option<&Block>,
+        Option<&Bullet>,
+    )>,
+    clock: Res<GameClock>,
+) {
+    // Nothing moves between ticks, so neither does the grid
+    if !clock.ticked() {
+        return;
+    }
+
+    spatial_hash.clear();
+
+    for (entity, collider, transform, block, bullet) in collider_query.iter() {
+        // Only the player collides without being a block or a bullet
+        let order = match (block, bullet) {
+            (Some(block), _) => ColliderOrder::Block(block.order()),
+            (None, Some(bullet)) => ColliderOrder::Bullet(bullet.order()),
+            (None, None) => ColliderOrder::Player,
+        };
+
+        spatial_hash.insert(entity, order, transform.translation.truncate(), collider);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn square(size: f32) -> Collider {
+        Collider::aabb(Vec2::splat(size))
+    }
+
+    // Every entity in these tests is a block, as old as its id
+    fn order(entity: Entity) -> ColliderOrder {
+        ColliderOrder::Block(entity.id() as u64)
+    }
+
+    // A 1000 by 1000 area split into 100 pixel cells
+    fn grid() -> SpatialHash {
+        SpatialHash::new(Vec2::splat(1000.0), 100.0)
+    }
+
+    #[test]
+    fn finds_entities_in_the_same_and_neighbouring_cells() {
+        let mut hash = grid();
+        let (a, b, c) = (Entity::new(0), Entity::new(1), Entity::new(2));
+
+        hash.insert(a, order(a), Vec2::new(10.0, 10.0), &square(20.0));
+        hash.insert(b, order(b), Vec2::new(40.0, 10.0), &square(20.0));
+        hash.insert(c, order(c), Vec2::new(400.0, 400.0), &square(20.0));
+
+        assert_eq!(hash.nearby(a, 0.0), vec![b]);
+        assert_eq!(hash.nearby(c, 0.0), Vec::<Entity>::new());
+    }
+
+    #[test]
+    fn radius_reaches_further_cells() {
+        let mut hash = grid();
+        let (a, b) = (Entity::new(0), Entity::new(1));
+
+        hash.insert(a, order(a), Vec2::new(50.0, 50.0), &square(20.0));
+        hash.insert(b, order(b), Vec2::new(250.0, 50.0), &square(20.0));
+
+        assert!(hash.nearby(a, 0.0).is_empty());
+        assert_eq!(hash.nearby(a, 150.0), vec![b]);
+    }
+
+    #[test]
+    fn entities_spanning_cells_are_found_once() {
+        let mut hash = grid();
+        let (a, b) = (Entity::new(0), Entity::new(1));
+
+        // Both sit on the corner where four cells meet
+        hash.insert(a, order(a), Vec2::ZERO, &square(40.0));
+        hash.insert(b, order(b), Vec2::new(5.0, 5.0), &square(40.0));
+
+        assert_eq!(hash.nearby(a, 0.0), vec![b]);
+        assert_eq!(hash.nearby(b, 0.0), vec![a]);
+    }
+
+    #[test]
+    fn wraps_around_the_edges() {
+        let mut hash = grid();
+        let (a, b, c) = (Entity::new(0), Entity::new(1), Entity::new(2));
+
+        hash.insert(a, order(a), Vec2::new(490.0, 0.0), &square(20.0));
+        hash.insert(b, order(b), Vec2::new(-490.0, 0.0), &square(20.0));
+        // Off the bottom of the grid, so it wraps to the top
+        hash.insert(c, order(c), Vec2::new(0.0, -520.0), &square(20.0));
+
+        assert_eq!(hash.nearby(a, 0.0), vec![b]);
+        assert_eq!(hash.nearby(b, 0.0), vec![a]);
+
+        let (d, e) = (Entity::new(3), Entity::new(4));
+        hash.insert(d, order(d), Vec2::new(0.0, 480.0), &square(20.0));
+        hash.insert(e, order(e), Vec2::new(0.0, 200.0), &square(20.0));
+        assert_eq!(hash.nearby(d, 0.0), vec![c]);
+    }
+
+    #[test]
+    fn huge_radius_visits_every_cell_once() {
+        let mut hash = grid();
+        let (a, b) = (Entity::new(0), Entity::new(1));
+
+        hash.insert(a, order(a), Vec2::ZERO, &square(10.0));
+        hash.insert(b, order(b), Vec2::new(300.0, -300.0), &square(10.0));
+
+        assert_eq!(hash.nearby(a, 10_000.0), vec![b]);
+        assert_eq!(
+            hash.cell_indices(Vec2::ZERO, Vec2::splat(10_000.0)).count(),
+            100
+        );
+    }
+
+    #[test]
+    fn clearing_forgets_everything() {
+        let mut hash = grid();
+        let (a, b) = (Entity::new(0), Entity::new(1));
+
+        hash.insert(a, order(a), Vec2::ZERO, &square(10.0));
+        hash.insert(b, order(b), Vec2::ZERO, &square(10.0));
+        hash.clear();
+
+        assert!(hash.nearby(a, 0.0).is_empty());
+    }
+
+    #[test]
+    fn nearby_entities_come_oldest_first() {
+        let mut hash = grid();
+        let (a, b, c) = (Entity::new(0), Entity::new(1), Entity::new(2));
+
+        hash.insert(a, ColliderOrder::Player, Vec2::ZERO, &square(20.0));
+        hash.insert(
+            b,
+            ColliderOrder::Block(2),
+            Vec2::new(5.0, 0.0),
+            &square(20.0),
+        );
+        hash.insert(
+            c,
+            ColliderOrder::Block(1),
+            Vec2::new(-5.0, 0.0),
+            &square(20.0),
+        );
+
+        assert_eq!(hash.nearby(a, 0.0), vec![c, b]);
+    }
+}
@@ -1,9 +1,13 @@
-use crate::{Direction, BLOCKSIZEX, BLOCKSIZEY, WINDOWHEIGHT, WINDOWWIDTH};
+use crate::config::GameConfig;
+use crate::Direction;
 
 use bevy::prelude::*;
 use rand::Rng;
+use std::convert::TryFrom;
 use std::fmt;
 
+use super::rng::GameRng;
+
 pub struct SpawningPlugin;
 
 impl Plugin for SpawningPlugin {
@@ -40,6 +44,7 @@ pub struct SpawnInfo {
     pub spawn_location: (i16, i16),
     pub spawn_direction: Direction,
     pub spawned: bool,
+    // Which edge the position is on, so a new direction stays along the same axis
     pub direction: BlockDirection,
 }
 
@@ -59,6 +64,16 @@ pub enum BlockDirection {
     Vertical,
 }
 
+impl BlockDirection {
+    // Left or right for horizontal blocks, up or down for vertical ones
+    pub fn random_direction<R: Rng>(self, rng: &mut R) -> Direction {
+        match self {
+            BlockDirection::Horizontal => get_direction(rng.gen_range(1..=2)),
+            BlockDirection::Vertical => get_direction(rng.gen_range(3..=4)),
+        }
+    }
+}
+
 // The edge of the screen is half of the total size
 fn get_edge_of_screen(window_size: f32) -> i16 {
     window_size as i16 / 2
@@ -67,60 +82,80 @@ fn get_edge_of_screen(window_size: f32) -> i16 {
 fn create_random_blocks<R: Rng>(
     block_positions: Vec<i16>,
     orientation: BlockDirection,
+    config: &GameConfig,
     rng: &mut R,
 ) -> Vec<SpawnInfo> {
-    return block_positions
+    block_positions
         .iter()
         .map(|block_position| SpawnInfo {
-            spawn_direction: match orientation {
-                BlockDirection::Horizontal => get_direction(rng.gen_range(1..=2)),
-                BlockDirection::Vertical => get_direction(rng.gen_range(3..=4)),
-            },
+            spawn_direction: orientation.random_direction(rng),
             spawn_location: match orientation {
                 // If you want the blocks to go horizontally (left/right) the y position needs to change
-                BlockDirection::Horizontal => (get_edge_of_screen(WINDOWWIDTH), *block_position),
+                BlockDirection::Horizontal => {
+                    (get_edge_of_screen(config.window_width), *block_position)
+                }
                 // If you want the blocks to go vertically (up/down) the x position needs to change
-                BlockDirection::Vertical => (*block_position, get_edge_of_screen(WINDOWHEIGHT)),
+                BlockDirection::Vertical => {
+                    (*block_position, get_edge_of_screen(config.window_height))
+                }
             },
             spawned: false,
             direction: orientation,
         })
-        .collect();
+        .collect()
+}
+
+// Every spacing along one side, from one edge of the screen onwards.
+// Positions too far off screen for an i16 are left out.
+fn spawn_positions(window_size: i32, count: i32, spacing: i16) -> Vec<i16> {
+    (0..=count)
+        .map(|index| window_size - index * spacing as i32 - window_size / 2)
+        .filter_map(|position| i16::try_from(position).ok())
+        .collect()
 }
 
 // Blocks should spawn in set locations depending on
 // the size of the screen.
-fn create_spawn_locations() -> Vec<SpawnInfo> {
+fn create_spawn_locations<R: Rng>(config: &GameConfig, rng: &mut R) -> Vec<SpawnInfo> {
+    // NOTE
+    // Worked out in i32 so big windows or tiny blocks can't overflow,
+    // only the final positions have to fit in an i16.
+    let window_width = config.window_width as i32;
+    let window_height = config.window_height as i32;
+
     // Calculate the number of blocks per side
     // ScreenLength / (BlockGap + BlockLength) = BlockNumber
     // Plus 1 because I want one block to spawn at the opposite edge
-    let blocks_per_width: i16 = WINDOWWIDTH as i16 / (45 + BLOCKSIZEX as i16);
-    let blocks_per_height: i16 = WINDOWHEIGHT as i16 / (45 + BLOCKSIZEY as i16);
+    // The block size is cut to whole pixels, and the config makes sure this is at least 1
+    let block_size = config.large_block.size;
+    let blocks_per_width =
+        (window_width as f32 / (config.spawn_gap as f32 + block_size.0.trunc())) as i32;
+    let blocks_per_height =
+        (window_height as f32 / (config.spawn_gap as f32 + block_size.1.trunc())) as i32;
 
     // Calculate the positions of the blocks per side
     // Need to divide by half because (0,0) is the middle of the screen
     // Vertical blocks have different x spawning positions
-    let vertical_block_positions: Vec<i16> = (0..=blocks_per_width)
-        .map(|x| WINDOWWIDTH as i16 - (x * 90))
-        .map(|x| x - get_edge_of_screen(WINDOWWIDTH))
-        .collect();
+    let vertical_block_positions =
+        spawn_positions(window_width, blocks_per_width, config.spawn_spacing);
 
     // Horizontal blocks have different y spawning positions
-    let horizontal_block_positions: Vec<i16> = (0..=blocks_per_height)
-        .map(|y| WINDOWHEIGHT as i16 - (y * 90))
-        .map(|y| y - get_edge_of_screen(WINDOWHEIGHT))
-        .collect();
-
-    let mut rng = rand::thread_rng();
+    let horizontal_block_positions =
+        spawn_positions(window_height, blocks_per_height, config.spawn_spacing);
 
     // Create the horizontal and vertical blocks
-    let random_horizontal_blocks: Vec<SpawnInfo> =
-        create_random_blocks(vertical_block_positions, BlockDirection::Vertical, &mut rng);
+    let random_horizontal_blocks: Vec<SpawnInfo> = create_random_blocks(
+        vertical_block_positions,
+        BlockDirection::Vertical,
+        config,
+        rng,
+    );
 
     let random_vertical_blocks: Vec<SpawnInfo> = create_random_blocks(
         horizontal_block_positions,
         BlockDirection::Horizontal,
-        &mut rng,
+        config,
+        rng,
     );
 
     // Combine the blocks together
@@ -137,8 +172,68 @@ fn create_spawn_locations() -> Vec<SpawnInfo> {
 //
 // TODO
 // In Bevy 0.6 this can be rewritten using IteratorCommands
-fn spawn_block_positions(mut commands: Commands) {
-    for spawn_location in create_spawn_locations() {
+fn spawn_block_positions(
+    mut commands: Commands,
+    mut rng: ResMut<GameRng>,
+    config: Res<GameConfig>,
+) {
+    for spawn_location in create_spawn_locations(&config, &mut *rng) {
         commands.spawn().insert(spawn_location);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::BlockTierConfig;
+
+    fn spawn_grid(seed: u64) -> Vec<String> {
+        create_spawn_locations(&GameConfig::default(), &mut GameRng::new(seed))
+            .iter()
+            .map(|location| location.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn seed_decides_the_spawn_directions() {
+        assert_eq!(spawn_grid(42), spawn_grid(42));
+        assert_ne!(spawn_grid(42), spawn_grid(43));
+
+        // Only the directions change, the positions come from the config
+        let locations = |seed| {
+            create_spawn_locations(&GameConfig::default(), &mut GameRng::new(seed))
+                .iter()
+                .map(|location| location.spawn_location)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(locations(42), locations(43));
+    }
+
+    // The biggest window the config allows, with a spawn position on every pixel.
+    // Working this out in i16 used to overflow.
+    #[test]
+    fn biggest_window_gets_a_full_spawn_grid() {
+        let config = GameConfig {
+            window_width: i16::MAX as f32,
+            window_height: i16::MAX as f32,
+            large_block: BlockTierConfig {
+                size: (1.0, 1.0),
+                ..GameConfig::default().large_block
+            },
+            spawn_gap: 0,
+            spawn_spacing: 1,
+            ..GameConfig::default()
+        };
+
+        let locations = create_spawn_locations(&config, &mut GameRng::new(0));
+
+        // One for every pixel along each side, plus the opposite edge
+        assert_eq!(locations.len(), 2 * (i16::MAX as usize + 1));
+
+        let half = (i16::MAX / 2 + 1) as i32;
+        for location in &locations {
+            let (x, y) = location.spawn_location;
+            assert!((x as i32).abs() <= half && (y as i32).abs() <= half);
+        }
+    }
+}
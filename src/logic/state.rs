@@ -0,0 +1,164 @@
+// GAME STATE CODE
+
+use bevy::prelude::*;
+
+use super::actions::{Action, InputActions};
+use super::difficulty::DifficultyPreset;
+use super::player::{ControlScheme, PlayerDeathEvent};
+use super::replay::Playback;
+use super::{GameSystem, RunSettings};
+
+pub struct GameStatePlugin;
+
+impl Plugin for GameStatePlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<LoadingProgress>()
+            .add_state(GameState::Loading)
+            .add_system_set(
+                SystemSet::on_update(GameState::Loading).with_system(finish_loading.system()),
+            )
+            .add_system_set(
+                SystemSet::on_update(GameState::Title)
+                    .with_system(start_game.system())
+                    .with_system(switch_controls.system())
+                    .with_system(switch_preset.system()),
+            )
+            // The controls and preset can be changed between runs
+            .add_system_set(
+                SystemSet::on_update(GameState::GameOver)
+                    .with_system(switch_controls.system())
+                    .with_system(switch_preset.system()),
+            )
+            .add_system_set(
+                SystemSet::on_enter(GameState::Playing)
+                    .with_system(apply_run_settings.system().label(RunSettings)),
+            )
+            .add_system_set(
+                SystemSet::on_update(GameState::Playing)
+                    .with_system(pause_game.system())
+                    .with_system(end_game.system().after(GameSystem::CollisionResponse)),
+            )
+            .add_system_set(
+                SystemSet::on_update(GameState::Paused).with_system(unpause_game.system()),
+            );
+    }
+}
+
+// Which part of the game we are in. Gameplay systems only
+// run while Playing, so Paused freezes the whole board.
+//
+// Loading -> Title -> Playing -> GameOver -> Playing -> ...
+// Paused is pushed on top of Playing and popped to resume it.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum GameState {
+    // Waiting for the textures, fonts and music before showing anything
+    Loading,
+    Title,
+    Playing,
+    Paused,
+    GameOver,
+}
+
+// How many of the game's assets have finished loading.
+// Headless games don't load any, so they are done straight away.
+#[derive(Default)]
+pub struct LoadingProgress {
+    pub loaded: usize,
+    pub total: usize,
+}
+
+impl LoadingProgress {
+    pub fn is_done(&self) -> bool {
+        self.loaded >= self.total
+    }
+}
+
+// Goes straight from loading to playing, for runs without anyone to press a key
+pub struct SkipTitle;
+
+fn finish_loading(
+    loading_progress: Res<LoadingProgress>,
+    skip_title: Option<Res<SkipTitle>>,
+    mut state: ResMut<State<GameState>>,
+) {
+    if !loading_progress.is_done() {
+        return;
+    }
+
+    let next_state = match skip_title {
+        Some(_) => GameState::Playing,
+        None => GameState::Title,
+    };
+
+    let _ = state.set(next_state);
+}
+
+// Every state change consumes the press that caused it,
+// see the NOTE in the actions module
+fn start_game(mut input_actions: ResMut<InputActions>, mut state: ResMut<State<GameState>>) {
+    if input_actions.consume(Action::Start) {
+        // Can only fail if another transition is queued this frame
+        let _ = state.set(GameState::Playing);
+    }
+}
+
+fn switch_controls(
+    mut input_actions: ResMut<InputActions>,
+    mut control_scheme: ResMut<ControlScheme>,
+) {
+    if input_actions.consume(Action::SwitchControls) {
+        *control_scheme = control_scheme.other();
+    }
+}
+
+fn switch_preset(mut input_actions: ResMut<InputActions>, mut preset: ResMut<DifficultyPreset>) {
+    if input_actions.consume(Action::SwitchPreset) {
+        *preset = preset.next();
+    }
+}
+
+// The control scheme and preset start out from the command line or config
+// and can be switched on the title and game over screens, so a normal run
+// plays with whatever they are now. A replay plays each run with the
+// settings it was recorded with instead.
+fn apply_run_settings(
+    playback: Option<Res<Playback>>,
+    mut control_scheme: ResMut<ControlScheme>,
+    mut preset: ResMut<DifficultyPreset>,
+) {
+    let settings = playback.and_then(|playback| playback.run_settings());
+
+    if let Some((recorded_control_scheme, recorded_preset)) = settings {
+        *control_scheme = recorded_control_scheme;
+        *preset = recorded_preset;
+    }
+}
+
+fn pause_game(mut input_actions: ResMut<InputActions>, mut state: ResMut<State<GameState>>) {
+    if input_actions.consume(Action::Pause) {
+        let _ = state.push(GameState::Paused);
+    }
+}
+
+fn unpause_game(mut input_actions: ResMut<InputActions>, mut state: ResMut<State<GameState>>) {
+    if input_actions.consume(Action::Pause) {
+        let _ = state.pop();
+    }
+}
+
+fn end_game(
+    mut player_death_event: EventReader<PlayerDeathEvent>,
+    mut state: ResMut<State<GameState>>,
+) {
+    if let Some(death) = player_death_event.iter().next() {
+        println!(
+            "Hit by a {} block at ({:.0}, {:.0}) after {:.1} seconds.",
+            format!("{:?}", death.tier).to_lowercase(),
+            death.position.x,
+            death.position.y,
+            death.time_survived
+        );
+
+        let _ = state.set(GameState::GameOver);
+    }
+}
@@ -0,0 +1,285 @@
+// TELEPORT CODE
+
+use bevy::prelude::*;
+
+use crate::config::GameConfig;
+use crate::Dimensions;
+
+use super::blocks::Block;
+use super::clock::GameClock;
+use super::collision::Collider;
+use super::input::PlayerInput;
+use super::player::{ControlScheme, Invulnerable, Player};
+use super::respawn::Respawning;
+use super::state::GameState;
+use super::{wrap_around_screen, GameSystem};
+
+// How much of the full distance a teleport tries, longest first,
+// when the full jump would land inside a block
+const TELEPORT_REACH: [f32; 4] = [1.0, 0.75, 0.5, 0.25];
+
+pub struct TeleportPlugin;
+
+impl Plugin for TeleportPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<Teleport>()
+            .add_system_set(
+                SystemSet::on_enter(GameState::Playing).with_system(reset_teleport.system()),
+            )
+            .add_system_set(
+                SystemSet::on_update(GameState::Playing).with_system(
+                    teleport_player
+                        .system()
+                        .label(GameSystem::Teleport)
+                        .after(GameSystem::Movement),
+                ),
+            );
+    }
+}
+
+// Teleports are spent from a meter that fills back up over time.
+// With one charge this is a plain cooldown.
+pub struct Teleport {
+    // How many teleports are stored, the part after the point is the next one filling up
+    pub charge: f32,
+    // Where a teleport would land right now, None if the player can't teleport.
+    // The graphics plugins show it as a preview.
+    pub target: Option<Vec2>,
+}
+
+impl FromWorld for Teleport {
+    fn from_world(world: &mut World) -> Self {
+        let config = world
+            .get_resource::<GameConfig>()
+            .expect("The game config is added before the plugins.");
+
+        Teleport::full(config)
+    }
+}
+
+impl Teleport {
+    fn full(config: &GameConfig) -> Self {
+        Teleport {
+            charge: config.teleport_charges as f32,
+            target: None,
+        }
+    }
+
+    // Whether at least one teleport is stored
+    pub fn ready(&self) -> bool {
+        self.charge >= 1.0
+    }
+}
+
+fn reset_teleport(mut teleport: ResMut<Teleport>, config: Res<GameConfig>) {
+    *teleport = Teleport::full(&config);
+}
+
+// NOTE
+// This runs after everything has moved, so the landing spot is
+// checked against where the blocks are on the tick the player arrives.
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+fn teleport_player(
+    mut commands: Commands,
+    mut player_query: Query<
+        (
+            Entity,
+            &Player,
+            &mut Transform,
+            &Dimensions,
+            &Collider,
+            Option<&Invulnerable>,
+        ),
+        Without<Respawning>,
+    >,
+    block_query: Query<(&Transform, &Collider), (With<Block>, Without<Player>)>,
+    mut teleport: ResMut<Teleport>,
+    player_input: Res<PlayerInput>,
+    control_scheme: Res<ControlScheme>,
+    clock: Res<GameClock>,
+    config: Res<GameConfig>,
+) {
+    if !clock.ticked() {
+        return;
+    }
+
+    let max_charge = config.teleport_charges as f32;
+    teleport.charge = if config.teleport_recharge > 0.0 {
+        (teleport.charge + clock.delta_seconds() / config.teleport_recharge).min(max_charge)
+    } else {
+        max_charge
+    };
+
+    let (entity, player, mut transform, dimensions, collider, invulnerable) =
+        match player_query.single_mut() {
+            Ok(player) => player,
+            Err(_) => {
+                teleport.target = None;
+                return;
+            }
+        };
+
+    let start = transform.translation;
+    let jump =
+        teleport_direction(player, &player_input, *control_scheme) * player.teleport_distance;
+    let blocks: Vec<(Vec2, Collider)> = block_query
+        .iter()
+        .map(|(transform, collider)| (transform.translation.truncate(), *collider))
+        .collect();
+
+    teleport.target = if teleport.ready() {
+        safe_destination(start, jump, dimensions.0, collider, &blocks, &config)
+    } else {
+        None
+    };
+
+    if !player_input.teleport {
+        return;
+    }
+
+    let destination = match teleport.target {
+        Some(destination) => destination,
+        None => return,
+    };
+
+    transform.translation = destination.extend(start.z);
+    teleport.charge -= 1.0;
+
+    // Blinking through keeps the player safe for a moment after landing,
+    // without cutting short any invulnerability they already have
+    let blink = config.teleport_blink;
+    let remaining = invulnerable
+        .map(|invulnerable| invulnerable.0.duration().as_secs_f32() - invulnerable.0.elapsed_secs())
+        .unwrap_or(0.0);
+
+    if blink > remaining {
+        commands
+            .entity(entity)
+            .insert(Invulnerable(Timer::from_seconds(blink, false)));
+    }
+}
+
+// Arcade teleports go the full distance along each held axis.
+// Without a key held they go the way the player last moved, or the way the ship points.
+pub fn teleport_direction(
+    player: &Player,
+    player_input: &PlayerInput,
+    control_scheme: ControlScheme,
+) -> Vec2 {
+    if control_scheme == ControlScheme::Arcade {
+        let x_axis = -(player_input.left as i8) + player_input.right as i8;
+        let y_axis = -(player_input.down as i8) + player_input.up as i8;
+
+        if x_axis != 0 || y_axis != 0 {
+            return Vec2::new(x_axis as f32, y_axis as f32);
+        }
+    }
+
+    player.facing
+}
+
+// The furthest of the teleport's reaches that doesn't land inside a block,
+// wrapped around the screen like any other movement
+pub fn safe_destination(
+    start: Vec3,
+    jump: Vec2,
+    size: Vec2,
+    player: &Collider,
+    blocks: &[(Vec2, Collider)],
+    config: &GameConfig,
+) -> Option<Vec2> {
+    TELEPORT_REACH.iter().find_map(|reach| {
+        let mut destination = start + (jump * *reach).extend(0.0);
+        wrap_around_screen(&mut destination, size, config);
+        let destination = destination.truncate();
+
+        let blocked = blocks
+            .iter()
+            .any(|(position, collider)| player.overlaps(destination, collider, *position));
+
+        if blocked {
+            None
+        } else {
+            Some(destination)
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn player() -> Collider {
+        Collider::aabb(Vec2::new(40.0, 40.0))
+    }
+
+    fn block(x: f32, y: f32) -> (Vec2, Collider) {
+        (Vec2::new(x, y), Collider::aabb(Vec2::new(40.0, 40.0)))
+    }
+
+    #[test]
+    fn jumps_the_full_distance_when_clear() {
+        let config = GameConfig::default();
+
+        let destination = safe_destination(
+            Vec3::ZERO,
+            Vec2::new(100.0, 0.0),
+            Vec2::new(40.0, 40.0),
+            &player(),
+            &[],
+            &config,
+        );
+
+        assert_eq!(destination, Some(Vec2::new(100.0, 0.0)));
+    }
+
+    #[test]
+    fn falls_short_of_a_block_at_the_destination() {
+        let config = GameConfig::default();
+
+        let destination = safe_destination(
+            Vec3::ZERO,
+            Vec2::new(100.0, 0.0),
+            Vec2::new(40.0, 40.0),
+            &player(),
+            &[block(100.0, 0.0)],
+            &config,
+        );
+
+        assert_eq!(destination, Some(Vec2::new(50.0, 0.0)));
+    }
+
+    #[test]
+    fn does_not_teleport_when_every_reach_is_blocked() {
+        let config = GameConfig::default();
+        let blocks = [block(25.0, 0.0), block(75.0, 0.0)];
+
+        let destination = safe_destination(
+            Vec3::ZERO,
+            Vec2::new(100.0, 0.0),
+            Vec2::new(40.0, 40.0),
+            &player(),
+            &blocks,
+            &config,
+        );
+
+        assert_eq!(destination, None);
+    }
+
+    #[test]
+    fn wraps_around_the_edge_of_the_screen() {
+        let config = GameConfig::default();
+        let start = Vec3::new(config.half_width() + 30.0, 0.0, 0.0);
+
+        let destination = safe_destination(
+            start,
+            Vec2::new(100.0, 0.0),
+            Vec2::new(40.0, 40.0),
+            &player(),
+            &[],
+            &config,
+        );
+
+        assert_eq!(destination, Some(Vec2::new(-config.half_width(), 0.0)));
+    }
+}
@@ -1,41 +1,190 @@
-use bevy::app::AppExit;
-use bevy::input::system::exit_on_esc_system;
+use bevy::app::{AppExit, Events, ManualEventReader};
+use bevy::input::InputPlugin;
 use bevy::prelude::*;
 use bevy_kira_audio::{Audio, AudioPlugin};
+use std::path::PathBuf;
+use std::process;
 
+mod assets;
+mod cli;
+mod config;
 mod graphics;
 mod logic;
+mod storage;
+#[cfg(test)]
+mod testing;
 
-const WINDOWHEIGHT: f32 = 1200.0;
-const WINDOWWIDTH: f32 = 1500.0;
-const BLOCKSIZEX: f32 = 40.0;
-const BLOCKSIZEY: f32 = 40.0;
+use assets::{AssetsPlugin, GameAsset, GameAssets};
+use config::{GameConfig, DEFAULT_CONFIG_PATH};
+use logic::actions::{key_bindings_path, KeyBindings};
+use logic::clock::GameClock;
+use logic::high_scores::PlayerName;
+use logic::input::DeviceInputPlugin;
+use logic::player::Player;
+use logic::replay::{Playback, Replay, ReplayPlaybackPlugin, ReplayRecorderPlugin};
+use logic::rng::GameRng;
+use logic::score::{Score, SurvivalTime};
+use logic::state::SkipTitle;
 
 fn main() {
-    App::build()
-        .insert_resource(WindowDescriptor {
-            title: "Asteroids Clone".to_string(),
-            width: WINDOWWIDTH,
-            height: WINDOWHEIGHT,
-            vsync: true,
-            ..Default::default()
-        })
-        .add_plugins(DefaultPlugins)
-        .add_plugin(AudioPlugin)
-        .add_startup_system(setup.system())
-        .add_startup_system(render_background.system())
-        .add_startup_system(start_background_audio.system())
-        // .add_startup_system(play_music.system())
-        // Turn on to see framerate, also import line above
-        // .add_plugin(FrameTimeDiagnosticsPlugin::default())
-        .add_plugin(logic::spawning::SpawningPlugin)
-        .add_plugin(logic::player::PlayerPlugin)
-        .add_plugin(logic::blocks::BlocksPlugin)
-        .add_plugin(logic::reset_game::ResetGamePlugin)
-        .add_plugin(graphics::score::ScorePlugin)
-        .add_plugin(graphics::text::TextPlugin)
-        .add_system(exit_on_esc_system.system())
-        .run();
+    let options = cli::Options::from_args();
+
+    let config_path = options
+        .config_path
+        .unwrap_or_else(|| PathBuf::from(DEFAULT_CONFIG_PATH));
+
+    let config = match GameConfig::load(&config_path) {
+        Ok(config) => config,
+        Err(error) => {
+            eprintln!("{}: {}", config_path.display(), error);
+            process::exit(1);
+        }
+    };
+
+    let replay = options.replay_path.map(|path| match Replay::load(&path) {
+        Ok(replay) => replay,
+        Err(error) => {
+            eprintln!("{}: {}", path.display(), error);
+            process::exit(1);
+        }
+    });
+
+    let headless = options.headless_ticks.is_some();
+    let mut app = App::build();
+
+    // NOTE
+    // Every resource has to be added before the plugins,
+    // some of them read resources while they are being built.
+    let seed = match &replay {
+        Some(replay) => {
+            if options.seed.is_some() {
+                println!("Ignoring --seed, the replay uses the seed it was recorded with.");
+            }
+
+            if replay.config_hash != config.gameplay_hash() {
+                println!("The replay was recorded with a different config, it will likely desync.");
+            }
+
+            Some(replay.seed)
+        }
+        None => options.seed.or(config.seed),
+    };
+
+    if let Some(seed) = seed {
+        app.insert_resource(GameRng::new(seed));
+    }
+
+    if let Some(player_name) = options.player_name.or_else(|| config.player_name.clone()) {
+        app.insert_resource(PlayerName(player_name));
+    }
+
+    // Replays set the control scheme and preset for each run they play back
+    app.insert_resource(options.control_scheme.unwrap_or(config.control_scheme))
+        .insert_resource(options.preset.unwrap_or(config.preset));
+
+    let replaying = replay.is_some();
+
+    // Nobody is there to press start
+    if headless || replaying {
+        app.insert_resource(SkipTitle);
+    }
+
+    if let Some(replay) = replay {
+        // Headless replays are checks, so they stop once the replay is over
+        app.insert_resource(Playback::new(replay, headless));
+    }
+
+    match options.headless_ticks {
+        Some(ticks) => add_headless_plugins(&mut app, config, ticks),
+        None => add_windowed_plugins(&mut app, config, replaying),
+    }
+
+    if replaying {
+        app.add_plugin(ReplayPlaybackPlugin);
+    } else {
+        app.add_plugin(DeviceInputPlugin);
+    }
+
+    app.run();
+}
+
+fn add_windowed_plugins(app: &mut AppBuilder, config: GameConfig, replaying: bool) {
+    app.insert_resource(WindowDescriptor {
+        title: "Asteroids Clone".to_string(),
+        width: config.window_width,
+        height: config.window_height,
+        vsync: true,
+        ..Default::default()
+    })
+    .insert_resource(config)
+    .insert_resource(KeyBindings::load(key_bindings_path()))
+    .add_plugins(DefaultPlugins)
+    .add_plugin(AudioPlugin)
+    .add_plugin(AssetsPlugin)
+    .add_startup_system(setup.system())
+    .add_startup_system(render_background.system())
+    .add_startup_system(start_background_audio.system())
+    // .add_startup_system(play_music.system())
+    // Turn on to see framerate, also import line above
+    // .add_plugin(FrameTimeDiagnosticsPlugin::default())
+    .add_plugins(logic::LogicPlugins)
+    .add_plugins(graphics::GraphicsPlugins);
+
+    // Watching a replay shouldn't record it again or earn high scores
+    if !replaying {
+        app.add_plugin(logic::high_scores::HighScorePlugin)
+            .add_plugin(ReplayRecorderPlugin);
+    }
+}
+
+// Runs the game logic without a window, renderer or audio.
+// Every update is one tick of the game clock, so a run
+// of N ticks always covers the same amount of game time.
+fn add_headless_plugins(app: &mut AppBuilder, config: GameConfig, ticks: u32) {
+    app.insert_resource(config)
+        .insert_resource(GameClock::headless())
+        .add_plugins(MinimalPlugins)
+        .add_plugin(InputPlugin)
+        .add_plugins(logic::LogicPlugins)
+        .set_runner(move |app| run_headless(app, ticks));
+}
+
+// Stops early if something asks the app to exit, like a finished replay
+fn run_headless(mut app: App, ticks: u32) {
+    let mut exit_reader = ManualEventReader::<AppExit>::default();
+    let mut ticks_run = 0;
+
+    while ticks_run < ticks {
+        app.update();
+        ticks_run += 1;
+
+        let exit_events = app
+            .world
+            .get_resource::<Events<AppExit>>()
+            .expect("The exit event is added by the core plugins.");
+        if exit_reader.iter(exit_events).next().is_some() {
+            break;
+        }
+    }
+
+    let mut players = app.world.query_filtered::<Entity, With<Player>>();
+    let player_alive = players.iter(&app.world).next().is_some();
+    let score = app
+        .world
+        .get_resource::<Score>()
+        .expect("The score resource is added by the score plugin.");
+    let survival_time = app
+        .world
+        .get_resource::<SurvivalTime>()
+        .expect("The survival time is added by the score plugin.");
+
+    println!(
+        "Simulated {} ticks, score is {} and the player is {}.",
+        ticks_run,
+        score.value,
+        if player_alive { "alive" } else { "dead" }
+    );
+    println!("{}", score.breakdown(survival_time));
 }
 
 fn setup(mut commands: Commands) {
@@ -45,15 +194,20 @@ fn setup(mut commands: Commands) {
 
 fn render_background(
     mut commands: Commands,
-    asset_server: Res<AssetServer>,
+    game_assets: Res<GameAssets>,
     mut materials: ResMut<Assets<ColorMaterial>>,
+    config: Res<GameConfig>,
 ) {
-    let background_image: Handle<Texture> = asset_server.load("textures/bg.png");
+    // The clear color is plain enough to play on
+    let background_image = match game_assets.texture(GameAsset::Background) {
+        Some(background_image) => background_image,
+        None => return,
+    };
 
     // Width of standard image in pixels is 272
-    let background_width = WINDOWWIDTH / 272.0;
+    let background_width = config.window_width / 272.0;
     // Width of standard image in pixels is 160
-    let background_height = WINDOWHEIGHT / 160.0;
+    let background_height = config.window_height / 160.0;
 
     commands.spawn_bundle(SpriteBundle {
         material: materials.add(background_image.into()),
@@ -69,8 +223,10 @@ fn exit_system(mut exit: EventWriter<AppExit>) {
     exit.send(AppExit);
 }
 
-fn start_background_audio(asset_server: Res<AssetServer>, audio: Res<Audio>) {
-    audio.play_looped(asset_server.load("sounds/bg_music.mp3"));
+fn start_background_audio(game_assets: Res<GameAssets>, audio: Res<Audio>) {
+    if let Some(music) = game_assets.music() {
+        audio.play_looped(music);
+    }
 }
 
 #[derive(Clone, Copy)]
@@ -81,4 +237,6 @@ pub enum Direction {
     Down,
 }
 
-struct Collidable;
+// The width and height of an entity in pixels.
+// Game logic uses this instead of the sprite size so it can run headless.
+pub struct Dimensions(pub Vec2);
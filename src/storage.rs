@@ -0,0 +1,24 @@
+// STORAGE CODE
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+// Where the game keeps files that should survive between sessions,
+// e.g. ~/.local/share/bevy_block_game on Linux
+pub fn data_dir() -> Option<PathBuf> {
+    dirs::data_dir().map(|directory| directory.join(env!("CARGO_PKG_NAME")))
+}
+
+// Write next to the real file first so a crash can't leave half a file behind
+pub fn write_atomically(path: &Path, contents: &str) -> io::Result<()> {
+    if let Some(directory) = path.parent() {
+        fs::create_dir_all(directory)?;
+    }
+
+    let mut temporary_path = path.as_os_str().to_owned();
+    temporary_path.push(".tmp");
+
+    fs::write(&temporary_path, contents)?;
+    fs::rename(&temporary_path, path)
+}
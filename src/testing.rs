@@ -0,0 +1,76 @@
+// TESTING CODE
+//
+// Helpers shared by the tests, none of this is built into the game.
+
+use bevy::input::InputPlugin;
+use bevy::prelude::*;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::config::GameConfig;
+use crate::logic::clock::{ClockPlugin, GameClock};
+use crate::logic::rng::GameRng;
+use crate::logic::state::SkipTitle;
+use crate::logic::LogicPlugins;
+
+// Just enough of an app to run game logic without a window.
+// Every update is one tick of the game clock, like a headless run.
+// Tests add the state and the plugins they need on top.
+pub fn headless_app(config: GameConfig) -> AppBuilder {
+    let mut builder = bare_app(config);
+    builder.add_plugin(ClockPlugin);
+    builder
+}
+
+// The whole simulation, the same as a headless run with the seed.
+// Starts playing on the first update, tests add where the input comes from.
+pub fn game_app(config: GameConfig, seed: u64) -> AppBuilder {
+    let mut builder = bare_app(config);
+    builder
+        .insert_resource(GameRng::new(seed))
+        .insert_resource(SkipTitle)
+        .add_plugins(LogicPlugins);
+    builder
+}
+
+fn bare_app(config: GameConfig) -> AppBuilder {
+    let mut builder = App::build();
+    builder
+        .insert_resource(config)
+        .insert_resource(GameClock::headless())
+        .add_plugins(MinimalPlugins)
+        .add_plugin(InputPlugin);
+    builder
+}
+
+// A folder of its own in the temp folder, deleted again once the test is done.
+// Tests run at the same time, so each one needs its own name.
+pub struct TemporaryDirectory(PathBuf);
+
+impl TemporaryDirectory {
+    pub fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!(
+            "{}_{}_{}",
+            env!("CARGO_PKG_NAME"),
+            std::process::id(),
+            name
+        ));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        TemporaryDirectory(path)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+
+    pub fn join<P: AsRef<Path>>(&self, path: P) -> PathBuf {
+        self.0.join(path)
+    }
+}
+
+impl Drop for TemporaryDirectory {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}